@@ -0,0 +1,204 @@
+use crate::response::RawApiResponse;
+use crate::{error::Error, error::Result};
+use async_trait::async_trait;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::fmt;
+use std::sync::Arc;
+
+/// Header used by `Auth::api_key` when no custom header is given.
+pub const DEFAULT_API_KEY_HEADER: &str = "x-api-key";
+
+/// Source of bearer tokens, queried before every request so implementations
+/// can cache and refresh tokens as needed.
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    async fn token(&self) -> Result<String>;
+}
+
+#[derive(Clone)]
+pub enum Auth {
+    /// Static key sent in `header`.
+    ApiKey { header: String, key: String },
+    /// Static bearer token.
+    Bearer(String),
+    /// Bearer token fetched from a provider on each request.
+    Provider(Arc<dyn TokenProvider>),
+}
+
+impl Auth {
+    pub fn api_key(key: &str) -> Self {
+        Auth::ApiKey {
+            header: DEFAULT_API_KEY_HEADER.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    pub fn api_key_header(header: &str, key: &str) -> Self {
+        Auth::ApiKey {
+            header: header.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    pub fn bearer(token: &str) -> Self {
+        Auth::Bearer(token.to_string())
+    }
+
+    pub fn provider<P: TokenProvider + 'static>(provider: P) -> Self {
+        Auth::Provider(Arc::new(provider))
+    }
+
+    pub(crate) async fn apply(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        match self {
+            Auth::ApiKey { header, key } => Ok(request.header(header.as_str(), key.as_str())),
+            Auth::Bearer(token) => Ok(request.bearer_auth(token)),
+            Auth::Provider(provider) => {
+                let token = provider.token().await?;
+                Ok(request.bearer_auth(token))
+            }
+        }
+    }
+}
+
+// Credentials are never printed.
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::ApiKey { header, .. } => write!(f, "Auth::ApiKey({})", header),
+            Auth::Bearer(_) => write!(f, "Auth::Bearer"),
+            Auth::Provider(_) => write!(f, "Auth::Provider"),
+        }
+    }
+}
+
+/// Maps 401/403 responses to `Error::AuthError`.
+pub(crate) async fn check_auth(response: Response) -> Result<Response> {
+    let code = response.status();
+    if code != StatusCode::UNAUTHORIZED && code != StatusCode::FORBIDDEN {
+        return Ok(response);
+    }
+
+    // Prefer the server's message, falling back to the raw body
+    let body = response.text().await?;
+    let message = match serde_json::from_str::<RawApiResponse>(&body) {
+        Ok(raw) => raw.message,
+        Err(_) => body,
+    };
+
+    Err(Error::AuthError {
+        code: code.as_u16(),
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn list_body() -> String {
+        json!({"status": "success", "message": "", "code": 200, "data": []}).to_string()
+    }
+
+    struct CountingProvider {
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl TokenProvider for CountingProvider {
+        async fn token(&self) -> Result<String> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(format!("token-{}", n))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_api_key() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/historical/instruments/list")
+            .match_header(DEFAULT_API_KEY_HEADER, "secret")
+            .with_status(200)
+            .with_body(list_body())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_auth(Auth::api_key("secret"));
+
+        // Test
+        let response = client.list_symbols().await?;
+
+        // Validate
+        assert_eq!(response.status, "success");
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_provider_refreshes() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("GET", "/historical/instruments/list")
+            .match_header("authorization", "Bearer token-0")
+            .with_status(200)
+            .with_body(list_body())
+            .create_async()
+            .await;
+        let second = server
+            .mock("GET", "/historical/instruments/list")
+            .match_header("authorization", "Bearer token-1")
+            .with_status(200)
+            .with_body(list_body())
+            .create_async()
+            .await;
+        let provider = CountingProvider {
+            calls: AtomicUsize::new(0),
+        };
+        let client = Historical::new(&server.url()).with_auth(Auth::provider(provider));
+
+        // Test
+        client.list_symbols().await?;
+        client.list_symbols().await?;
+
+        // Validate
+        first.assert_async().await;
+        second.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(401)
+            .with_body(
+                json!({"status": "failed", "message": "Invalid token", "code": 401}).to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_auth(Auth::bearer("expired"));
+
+        // Test
+        let result = client.list_symbols().await;
+
+        // Validate
+        match result {
+            Err(Error::AuthError { code, message }) => {
+                assert_eq!(code, 401);
+                assert_eq!(message, "Invalid token");
+            }
+            other => panic!("Expected auth error, got {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_hides_secret() {
+        let auth = Auth::api_key("secret");
+
+        // Validate
+        assert!(!format!("{:?}", auth).contains("secret"));
+    }
+}
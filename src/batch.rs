@@ -0,0 +1,375 @@
+use crate::error::Result;
+use crate::stream::RecordBuffer;
+use crate::upload::UPLOAD_CHUNK_SIZE;
+use crate::utils::write_atomic;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Header carrying the key of each uploaded batch.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Controls how `create_mbp_batched` splits its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchOptions {
+    /// Upper bound on the record bytes in one batch. A single record larger
+    /// than this is still sent, in a batch of its own.
+    pub max_batch_bytes: usize,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions {
+            max_batch_bytes: 8 << 20,
+        }
+    }
+}
+
+impl BatchOptions {
+    pub fn new(max_batch_bytes: usize) -> Self {
+        BatchOptions {
+            max_batch_bytes: max_batch_bytes.max(1),
+        }
+    }
+}
+
+/// Outcome of a `create_mbp_batched` run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub batches: usize,
+    pub uploaded: usize,
+    /// Batches skipped because the journal shows them as accepted.
+    pub skipped: usize,
+    pub records: u64,
+}
+
+/// Consecutive whole records from the input, uploaded as one request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Batch {
+    /// Position of the first record's bytes in the input.
+    pub offset: u64,
+    pub bytes: Vec<u8>,
+    pub records: u64,
+}
+
+/// Splits a records-only mbn byte stream into consecutive batches of whole
+/// records, holding at most one batch and one read in memory.
+pub(crate) struct Batcher<R> {
+    reader: R,
+    buffer: RecordBuffer,
+    current: Batch,
+    /// Input bytes consumed into batches so far.
+    offset: u64,
+    max_batch_bytes: usize,
+    read_size: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> Batcher<R> {
+    pub(crate) fn new(reader: R, options: &BatchOptions) -> Self {
+        Batcher {
+            reader,
+            buffer: RecordBuffer::without_metadata(),
+            current: Batch::default(),
+            offset: 0,
+            max_batch_bytes: options.max_batch_bytes,
+            read_size: UPLOAD_CHUNK_SIZE,
+            done: false,
+        }
+    }
+
+    /// Returns the next batch, or `None` once the input is exhausted.
+    pub(crate) async fn next_batch(&mut self) -> Result<Option<Batch>> {
+        let mut chunk = vec![0; self.read_size];
+
+        loop {
+            while let Some(bytes) = self.buffer.next_record_bytes()? {
+                let full = self.current.records > 0
+                    && self.current.bytes.len() + bytes.len() > self.max_batch_bytes;
+                let batch = full.then(|| std::mem::take(&mut self.current));
+
+                if self.current.records == 0 {
+                    self.current.offset = self.offset;
+                }
+                self.offset += bytes.len() as u64;
+                self.current.bytes.extend_from_slice(bytes);
+                self.current.records += 1;
+                if batch.is_some() {
+                    return Ok(batch);
+                }
+            }
+
+            if self.done {
+                self.buffer.finish()?;
+                let batch = std::mem::take(&mut self.current);
+                return Ok((batch.records > 0).then_some(batch));
+            }
+
+            match self.reader.read(&mut chunk).await? {
+                0 => self.done = true,
+                read => self.buffer.push(&chunk[..read]),
+            }
+        }
+    }
+}
+
+/// Idempotency key of a batch, derived from its position in the input and its
+/// content. Reruns over the same data produce the same keys, while identical
+/// batches at different positions stay distinct.
+pub(crate) fn batch_key(batch: &Batch) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(batch.offset.to_be_bytes());
+    hasher.update(&batch.bytes);
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Keys of the batches the server has acknowledged, saved between runs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Journal {
+    pub acknowledged: BTreeSet<String>,
+}
+
+impl Journal {
+    pub(crate) async fn load(path: &Path) -> Result<Self> {
+        match fs::read(path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Journal::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the journal atomically, so a crash never leaves a partial one.
+    pub(crate) async fn save(&self, path: &Path) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec(self)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use crate::retry::RetryPolicy;
+    use crate::stream::tests::{mbp, records};
+    use crate::Error;
+    use mbn::records::Mbp1Msg;
+    use serde_json::json;
+    use std::mem;
+    use std::time::Duration;
+
+    /// Key of the single-record batch at `offset` in `data`.
+    fn key(data: &[u8], offset: usize, size: usize) -> String {
+        batch_key(&Batch {
+            offset: offset as u64,
+            bytes: data[offset..offset + size].to_vec(),
+            records: 1,
+        })
+    }
+
+    fn success() -> String {
+        json!({"status": "success", "message": "", "code": 200, "data": ""}).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_batcher() -> Result<()> {
+        let data = records(&[mbp(1, 1), mbp(1, 2), mbp(1, 3)]);
+        let size = mem::size_of::<Mbp1Msg>();
+        let mut batcher = Batcher::new(data.as_slice(), &BatchOptions::new(2 * size + 1));
+        // Reads smaller than a record, so records span reads
+        batcher.read_size = 7;
+
+        // Test
+        let first = batcher.next_batch().await?;
+        let second = batcher.next_batch().await?;
+        let end = batcher.next_batch().await?;
+
+        // Validate
+        assert_eq!(
+            first,
+            Some(Batch {
+                offset: 0,
+                bytes: data[..2 * size].to_vec(),
+                records: 2,
+            })
+        );
+        assert_eq!(
+            second,
+            Some(Batch {
+                offset: 2 * size as u64,
+                bytes: data[2 * size..].to_vec(),
+                records: 1,
+            })
+        );
+        assert_eq!(end, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_key() {
+        let bytes = records(&[mbp(1, 1)]);
+        let batch = |offset: u64| Batch {
+            offset,
+            bytes: bytes.clone(),
+            records: 1,
+        };
+
+        // Validate
+        assert_eq!(batch_key(&batch(0)), batch_key(&batch(0)));
+        assert_ne!(batch_key(&batch(0)), batch_key(&batch(bytes.len() as u64)));
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_batched_resume() -> Result<()> {
+        let data = records(&[mbp(1, 1), mbp(1, 2)]);
+        let size = mem::size_of::<Mbp1Msg>();
+        let first_key = key(&data, 0, size);
+        let second_key = key(&data, size, size);
+        let journal = std::env::temp_dir().join("midas_test_batched.journal");
+        let _ = fs::remove_file(&journal).await;
+
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header(IDEMPOTENCY_KEY_HEADER, first_key.as_str())
+            .with_status(200)
+            .with_body(success())
+            .expect(1)
+            .create_async()
+            .await;
+        let failing = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header(IDEMPOTENCY_KEY_HEADER, second_key.as_str())
+            .with_status(500)
+            .with_body(json!({"status": "failed", "message": "Crashed", "code": 500}).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let options = BatchOptions::new(size);
+
+        // Test
+        let result = client
+            .create_mbp_batched(data.as_slice(), &options, &journal)
+            .await;
+
+        // Validate
+        assert!(matches!(result, Err(Error::ServerError { .. })));
+        first.assert_async().await;
+        failing.assert_async().await;
+
+        // Rerun, the first batch is skipped
+        failing.remove_async().await;
+        let second = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header(IDEMPOTENCY_KEY_HEADER, second_key.as_str())
+            .with_status(200)
+            .with_body(success())
+            .expect(1)
+            .create_async()
+            .await;
+
+        let summary = client
+            .create_mbp_batched(data.as_slice(), &options, &journal)
+            .await?;
+
+        second.assert_async().await;
+        assert_eq!(
+            summary,
+            BatchSummary {
+                batches: 2,
+                uploaded: 1,
+                skipped: 1,
+                records: 2,
+            }
+        );
+
+        // Cleanup
+        let _ = fs::remove_file(&journal).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_batched_identical_batches() -> Result<()> {
+        let data = records(&[mbp(1, 1), mbp(1, 1)]);
+        let size = mem::size_of::<Mbp1Msg>();
+        let journal = std::env::temp_dir().join("midas_test_batched_identical.journal");
+        let _ = fs::remove_file(&journal).await;
+
+        let mut server = mockito::Server::new_async().await;
+        let mut mocks = Vec::new();
+        for offset in [0, size] {
+            let mock = server
+                .mock("POST", "/historical/mbp/create")
+                .match_header(IDEMPOTENCY_KEY_HEADER, key(&data, offset, size).as_str())
+                .with_status(200)
+                .with_body(success())
+                .expect(1)
+                .create_async()
+                .await;
+            mocks.push(mock);
+        }
+        let client = Historical::new(&server.url());
+
+        // Test
+        let summary = client
+            .create_mbp_batched(data.as_slice(), &BatchOptions::new(size), &journal)
+            .await?;
+
+        // Validate
+        for mock in mocks {
+            mock.assert_async().await;
+        }
+        assert_eq!(summary.uploaded, 2);
+        assert_eq!(summary.skipped, 0);
+
+        // Cleanup
+        let _ = fs::remove_file(&journal).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_batched_retries() -> Result<()> {
+        let data = records(&[mbp(1, 1)]);
+        let journal = std::env::temp_dir().join("midas_test_batched_retry.journal");
+        let _ = fs::remove_file(&journal).await;
+
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(503)
+            .expect(1)
+            .create_async()
+            .await;
+        let accepted = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(200)
+            .with_body(success())
+            .expect(1)
+            .create_async()
+            .await;
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::ZERO)
+            .jitter(false);
+        let client = Historical::new(&server.url()).with_retry(policy);
+
+        // Test
+        let summary = client
+            .create_mbp_batched(data.as_slice(), &BatchOptions::default(), &journal)
+            .await?;
+
+        // Validate
+        unavailable.assert_async().await;
+        accepted.assert_async().await;
+        assert_eq!(summary.uploaded, 1);
+
+        // Cleanup
+        let _ = fs::remove_file(&journal).await;
+        Ok(())
+    }
+}
@@ -0,0 +1,454 @@
+use crate::chunk::Stitcher;
+use crate::error::Result;
+use crate::historical::RetrieveParams;
+use crate::stream::{decode_record, ts_recv, RecordBuffer};
+use crate::utils::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheKey {
+    symbols: Vec<String>,
+    schema: String,
+}
+
+impl CacheKey {
+    fn new(params: &RetrieveParams) -> Self {
+        let mut symbols = params.symbols.clone();
+        symbols.sort();
+        symbols.dedup();
+
+        CacheKey {
+            symbols,
+            schema: params.schema.clone(),
+        }
+    }
+}
+
+/// A cached response covering `[start_ts, end_ts)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    key: CacheKey,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub size: u64,
+    file: String,
+    last_used: u64,
+}
+
+impl CacheEntry {
+    fn overlaps(&self, key: &CacheKey, start: i64, end: i64) -> bool {
+        self.key == *key && self.start_ts < end && start < self.end_ts
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheIndex {
+    entries: Vec<CacheEntry>,
+    next_id: u64,
+    /// Incremented on every use, ordering entries for eviction.
+    clock: u64,
+}
+
+/// On-disk cache of `get_records` responses, keyed by symbols, schema and
+/// time window.
+///
+/// Each fetched window is stored as an mbn file in `dir`, alongside an
+/// `index.json` describing them. Requests overlapping cached windows only
+/// fetch the missing gaps. Windows the server had no data for are cached
+/// as empty. Only windows ending before the request is made are cached.
+/// Cached data is never refreshed, use `invalidate` after corrections on
+/// the server.
+#[derive(Debug)]
+pub struct RecordCache {
+    dir: PathBuf,
+    max_bytes: Option<u64>,
+    index: CacheIndex,
+}
+
+impl RecordCache {
+    /// Opens the cache in `dir`, creating it if needed.
+    pub async fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).await?;
+
+        let index = match fs::read(dir.join(INDEX_FILE)).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CacheIndex::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(RecordCache {
+            dir,
+            max_bytes: None,
+            index,
+        })
+    }
+
+    /// Evicts the least recently used windows once the cache exceeds `max_bytes`.
+    pub fn max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Total size of the cached files.
+    pub fn size(&self) -> u64 {
+        self.index.entries.iter().map(|e| e.size).sum()
+    }
+
+    pub fn entries(&self) -> &[CacheEntry] {
+        &self.index.entries
+    }
+
+    /// Parts of the requested range not covered by cached windows.
+    pub fn gaps(&self, params: &RetrieveParams) -> Vec<(i64, i64)> {
+        let key = CacheKey::new(params);
+        let (start, end) = (params.start_ts, params.end_ts);
+
+        let mut covered: Vec<(i64, i64)> = self
+            .index
+            .entries
+            .iter()
+            .filter(|e| e.overlaps(&key, start, end))
+            .map(|e| (e.start_ts, e.end_ts))
+            .collect();
+        covered.sort();
+
+        let mut gaps = Vec::new();
+        let mut cursor = start;
+        for (covered_start, covered_end) in covered {
+            if covered_start > cursor {
+                gaps.push((cursor, covered_start.min(end)));
+            }
+            cursor = cursor.max(covered_end);
+            if cursor >= end {
+                break;
+            }
+        }
+        if cursor < end {
+            gaps.push((cursor, end));
+        }
+        gaps
+    }
+
+    /// Stores the response for the window covered by `params`.
+    pub(crate) async fn insert(&mut self, params: &RetrieveParams, data: &[u8]) -> Result<()> {
+        let file = format!("{}.bin", self.index.next_id);
+        fs::write(self.dir.join(&file), data).await?;
+
+        self.index.next_id += 1;
+        self.index.clock += 1;
+        self.index.entries.push(CacheEntry {
+            key: CacheKey::new(params),
+            start_ts: params.start_ts,
+            end_ts: params.end_ts,
+            size: data.len() as u64,
+            file,
+            last_used: self.index.clock,
+        });
+        self.save().await
+    }
+
+    /// Assembles the requested range from cached windows, clipping records
+    /// to `[start_ts, end_ts)` on the same timestamp the server filters on.
+    pub(crate) async fn read(&mut self, params: &RetrieveParams) -> Result<Vec<u8>> {
+        let key = CacheKey::new(params);
+        let (start, end) = (params.start_ts, params.end_ts);
+        let mut stitcher = Stitcher::new(params);
+        let mut records = Vec::new();
+
+        self.index.clock += 1;
+        let clock = self.index.clock;
+        let mut entries: Vec<&mut CacheEntry> = self
+            .index
+            .entries
+            .iter_mut()
+            .filter(|e| e.overlaps(&key, start, end))
+            .collect();
+        entries.sort_by_key(|e| e.start_ts);
+
+        for entry in entries {
+            entry.last_used = clock;
+            let data = fs::read(self.dir.join(&entry.file)).await?;
+
+            // Windows are disjoint, so clipping to each window also drops duplicates
+            let from = start.max(entry.start_ts) as u64;
+            let to = end.min(entry.end_ts) as u64;
+            let mut buffer = RecordBuffer::without_metadata();
+            buffer.push(stitcher.add(&data)?);
+            while let Some(bytes) = buffer.next_record_bytes()? {
+                let ts = ts_recv(&decode_record(bytes)?);
+                if ts >= from && ts < to {
+                    records.extend_from_slice(bytes);
+                }
+            }
+            buffer.finish()?;
+        }
+        self.save().await?;
+
+        let mut output = stitcher.header()?;
+        output.extend_from_slice(&records);
+        Ok(output)
+    }
+
+    /// Drops cached windows overlapping the range of `params` for its symbols
+    /// and schema, returning how many were removed.
+    pub async fn invalidate(&mut self, params: &RetrieveParams) -> Result<usize> {
+        let key = CacheKey::new(params);
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.index.entries)
+            .into_iter()
+            .partition(|e| e.overlaps(&key, params.start_ts, params.end_ts));
+        self.index.entries = kept;
+
+        for entry in &removed {
+            self.remove_file(entry).await?;
+        }
+        self.save().await?;
+        Ok(removed.len())
+    }
+
+    /// Removes every cached window.
+    pub async fn clear(&mut self) -> Result<()> {
+        for entry in std::mem::take(&mut self.index.entries) {
+            self.remove_file(&entry).await?;
+        }
+        self.save().await
+    }
+
+    /// Removes least recently used windows until the cache fits `max_bytes`.
+    pub async fn evict(&mut self) -> Result<()> {
+        let max_bytes = match self.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return Ok(()),
+        };
+
+        self.index.entries.sort_by_key(|e| e.last_used);
+        let mut size = self.size();
+        let mut evicted = Vec::new();
+        while size > max_bytes && !self.index.entries.is_empty() {
+            let entry = self.index.entries.remove(0);
+            size -= entry.size;
+            evicted.push(entry);
+        }
+
+        for entry in &evicted {
+            self.remove_file(entry).await?;
+        }
+        self.save().await
+    }
+
+    async fn remove_file(&self, entry: &CacheEntry) -> Result<()> {
+        match fs::remove_file(self.dir.join(&entry.file)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn save(&self) -> Result<()> {
+        write_atomic(self.dir.join(INDEX_FILE), &serde_json::to_vec(&self.index)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use crate::stream::read_header;
+    use crate::stream::tests::{encoded, mbp, params};
+    use mbn::enums::Schema;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn timestamps(data: &[u8]) -> Result<Vec<u64>> {
+        let mut buffer = RecordBuffer::new();
+        buffer.push(data);
+        let mut timestamps = Vec::new();
+        while let Some(record) = buffer.next_record_bytes()? {
+            timestamps.push(read_header(record).unwrap().ts_event);
+        }
+        Ok(timestamps)
+    }
+
+    async fn cache(name: &str) -> Result<RecordCache> {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir).await;
+        RecordCache::open(dir).await
+    }
+
+    #[tokio::test]
+    async fn test_gaps() -> Result<()> {
+        let mut cache = cache("midas_client_cache_gaps").await?;
+        cache.insert(&params(10, 20), &[]).await?;
+        cache.insert(&params(30, 40), &[]).await?;
+
+        // Validate
+        assert_eq!(
+            cache.gaps(&params(0, 50)),
+            vec![(0, 10), (20, 30), (40, 50)]
+        );
+        assert_eq!(cache.gaps(&params(12, 18)), vec![]);
+        let mut other = params(10, 20);
+        other.schema = Schema::Trade.to_string();
+        assert_eq!(cache.gaps(&other), vec![(10, 20)]);
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_records_cached_fetches_gaps() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 0, "end_ts": 20})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 5), mbp(1, 15)]))
+            .expect(1)
+            .create_async()
+            .await;
+        let gap = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 20, "end_ts": 30})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 25)]))
+            .expect(1)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let mut cache = cache("midas_client_cache_gaps_fetch").await?;
+
+        // Test
+        let full = client
+            .get_records_cached(&params(0, 20), &mut cache)
+            .await?;
+        let overlap = client
+            .get_records_cached(&params(10, 30), &mut cache)
+            .await?;
+
+        // Validate
+        assert_eq!(timestamps(&full.data)?, vec![5, 15]);
+        assert_eq!(timestamps(&overlap.data)?, vec![15, 25]);
+        first.assert_async().await;
+        gap.assert_async().await;
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_read_clips_on_ts_recv() -> Result<()> {
+        let mut late = mbp(1, 5);
+        late.ts_recv = 15;
+        let mut cache = cache("midas_client_cache_ts_recv").await?;
+        cache.insert(&params(0, 20), &encoded(&[late])).await?;
+
+        // Test
+        let early = cache.read(&params(0, 10)).await?;
+        let later = cache.read(&params(10, 20)).await?;
+
+        // Validate
+        assert_eq!(timestamps(&early)?, vec![]);
+        assert_eq!(timestamps(&later)?, vec![5]);
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_records_cached_empty_window() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let empty = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(404)
+            .with_body(json!({"status": "failed", "message": "No data", "code": 404}).to_string())
+            .expect(1)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let mut cache = cache("midas_client_cache_empty").await?;
+
+        // Test
+        client
+            .get_records_cached(&params(0, 10), &mut cache)
+            .await?;
+        let response = client
+            .get_records_cached(&params(0, 10), &mut cache)
+            .await?;
+
+        // Validate
+        empty.assert_async().await;
+        assert_eq!(timestamps(&response.data)?, vec![]);
+        assert!(cache.gaps(&params(0, 10)).is_empty());
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_records_cached_skips_open_windows() -> Result<()> {
+        let now = chrono::Utc::now().timestamp_nanos_opt().unwrap();
+        let hour = 3_600_000_000_000;
+        let mut server = mockito::Server::new_async().await;
+        let open = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, (now + hour) as u64)]))
+            .expect(2)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let mut cache = cache("midas_client_cache_open").await?;
+
+        // Test
+        let window = params(now + hour, now + 2 * hour);
+        client.get_records_cached(&window, &mut cache).await?;
+        let response = client.get_records_cached(&window, &mut cache).await?;
+
+        // Validate
+        open.assert_async().await;
+        assert_eq!(timestamps(&response.data)?, vec![(now + hour) as u64]);
+        assert!(cache.entries().is_empty());
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_eviction_and_invalidate() -> Result<()> {
+        let data = encoded(&[mbp(1, 1)]);
+        let mut cache = cache("midas_client_cache_evict")
+            .await?
+            .max_bytes(data.len() as u64 * 2);
+        cache.insert(&params(0, 10), &data).await?;
+        cache.insert(&params(10, 20), &data).await?;
+        cache.insert(&params(20, 30), &data).await?;
+
+        // Test
+        cache.read(&params(0, 10)).await?;
+        cache.evict().await?;
+
+        // Validate: the least recently used window is dropped
+        assert_eq!(cache.gaps(&params(0, 30)), vec![(10, 20)]);
+
+        // Test
+        let removed = cache.invalidate(&params(0, 5)).await?;
+
+        // Validate
+        assert_eq!(removed, 1);
+        assert_eq!(cache.gaps(&params(0, 30)), vec![(0, 20)]);
+        let reopened = RecordCache::open(&cache.dir).await?;
+        assert_eq!(reopened.entries().len(), 1);
+
+        // Cleanup
+        fs::remove_dir_all(&cache.dir).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use crate::historical::RetrieveParams;
+use crate::utils::{naive_to_unix_nanos, parse_timezone};
+use crate::{error::Error, error::Result};
+use chrono::{Datelike, Days, NaiveDate, NaiveTime, Weekday};
+use mbn::enums::Schema;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+fn default_weekdays() -> Vec<Weekday> {
+    vec![
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+    ]
+}
+
+/// Session hours, holidays and early closes for one exchange, in exchange
+/// local time.
+///
+/// A session whose `open` is at or after its `close` starts the previous
+/// calendar day, e.g. CME Globex opening at 17:00 for the next day's session.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExchangeCalendar {
+    /// IANA timezone, e.g. `America/Chicago`.
+    pub timezone: String,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+    /// Days sessions close on, Monday to Friday unless set.
+    #[serde(default = "default_weekdays")]
+    pub weekdays: Vec<Weekday>,
+    #[serde(default)]
+    pub holidays: BTreeSet<NaiveDate>,
+    /// Close time for shortened sessions.
+    #[serde(default)]
+    pub early_closes: HashMap<NaiveDate, NaiveTime>,
+}
+
+impl ExchangeCalendar {
+    pub fn new(timezone: &str, open: NaiveTime, close: NaiveTime) -> Result<Self> {
+        parse_timezone(timezone)?;
+
+        Ok(ExchangeCalendar {
+            timezone: timezone.to_string(),
+            open,
+            close,
+            weekdays: default_weekdays(),
+            holidays: BTreeSet::new(),
+            early_closes: HashMap::new(),
+        })
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        self.weekdays.contains(&date.weekday()) && !self.holidays.contains(&date)
+    }
+
+    /// Session closing on `date` as `(open, close)` nanoseconds, or `None` if
+    /// the exchange is closed that day.
+    pub fn session(&self, date: NaiveDate) -> Result<Option<(i64, i64)>> {
+        if !self.is_trading_day(date) {
+            return Ok(None);
+        }
+
+        let tz = parse_timezone(&self.timezone)?;
+        let open_date = if self.open >= self.close {
+            date.checked_sub_days(Days::new(1))
+                .ok_or_else(|| Error::Validation(format!("No session before {}", date)))?
+        } else {
+            date
+        };
+        let close = self.early_closes.get(&date).copied().unwrap_or(self.close);
+
+        let open_ts = naive_to_unix_nanos(open_date.and_time(self.open), tz)?;
+        let close_ts = naive_to_unix_nanos(date.and_time(close), tz)?;
+        Ok(Some((open_ts, close_ts)))
+    }
+
+    /// Sessions closing within `dates`, skipping weekends and holidays.
+    pub fn sessions(&self, dates: RangeInclusive<NaiveDate>) -> Result<Vec<(i64, i64)>> {
+        let mut sessions = Vec::new();
+        for date in dates.start().iter_days().take_while(|d| d <= dates.end()) {
+            if let Some(session) = self.session(date)? {
+                sessions.push(session);
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+/// Calendars keyed by exchange, e.g. `CME`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Calendars {
+    exchanges: HashMap<String, ExchangeCalendar>,
+}
+
+impl Calendars {
+    /// Loads calendars from a `.toml` or `.json` file with one table per exchange.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("json") => Self::from_json(&contents),
+            _ => Err(Error::CustomError(format!(
+                "Unsupported calendar file '{}', expected .toml or .json",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let calendars: Calendars = toml::from_str(contents)?;
+        calendars.validate()
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let calendars: Calendars = serde_json::from_str(contents)?;
+        calendars.validate()
+    }
+
+    fn validate(self) -> Result<Self> {
+        for calendar in self.exchanges.values() {
+            parse_timezone(&calendar.timezone)?;
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, exchange: &str) -> Option<&ExchangeCalendar> {
+        self.exchanges.get(exchange)
+    }
+
+    pub fn insert(&mut self, exchange: &str, calendar: ExchangeCalendar) {
+        self.exchanges.insert(exchange.to_string(), calendar);
+    }
+}
+
+/// One request per session closing within `dates`, so only in-session data
+/// is retrieved.
+pub fn params_for_sessions(
+    symbols: Vec<String>,
+    schema: Schema,
+    dates: RangeInclusive<NaiveDate>,
+    calendar: &ExchangeCalendar,
+) -> Result<Vec<RetrieveParams>> {
+    calendar
+        .sessions(dates)?
+        .into_iter()
+        .map(|(start, end)| {
+            RetrieveParams::builder()
+                .symbols(symbols.iter().cloned())
+                .start(start)
+                .end(end)
+                .schema(schema)
+                .build()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::date_to_unix_nanos;
+
+    const CALENDARS: &str = r#"
+        [CME]
+        timezone = "America/Chicago"
+        open = "17:00:00"
+        close = "16:00:00"
+        weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+        holidays = ["2024-12-25"]
+
+        [CME.early_closes]
+        2024-12-24 = "12:15:00"
+
+        [NYSE]
+        timezone = "America/New_York"
+        open = "09:30:00"
+        close = "16:00:00"
+    "#;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_load_toml() -> Result<()> {
+        // Test
+        let calendars = Calendars::from_toml(CALENDARS)?;
+
+        // Validate
+        let cme = calendars.get("CME").expect("Missing CME");
+        assert!(cme.holidays.contains(&date(2024, 12, 25)));
+        assert_eq!(cme.weekdays.len(), 5);
+        assert!(calendars.get("NYSE").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_regular_hours() -> Result<()> {
+        let calendars = Calendars::from_toml(CALENDARS)?;
+        let nyse = calendars.get("NYSE").unwrap();
+
+        // Test
+        let session = nyse.session(date(2024, 1, 2))?;
+
+        // Validate
+        assert_eq!(
+            session,
+            Some((
+                date_to_unix_nanos("2024-01-02T14:30:00Z")?,
+                date_to_unix_nanos("2024-01-02T21:00:00Z")?
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_overnight_and_early_close() -> Result<()> {
+        let calendars = Calendars::from_toml(CALENDARS)?;
+        let cme = calendars.get("CME").unwrap();
+
+        // Test
+        let session = cme.session(date(2024, 12, 24))?;
+
+        // Validate
+        assert_eq!(
+            session,
+            Some((
+                date_to_unix_nanos("2024-12-23 17:00 America/Chicago")?,
+                date_to_unix_nanos("2024-12-24 12:15 America/Chicago")?
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_for_sessions_skips_closed_days() -> Result<()> {
+        let calendars = Calendars::from_toml(CALENDARS)?;
+        let cme = calendars.get("CME").unwrap();
+
+        // Test
+        let params = params_for_sessions(
+            vec!["HE.n.0".to_string()],
+            Schema::Ohlcv1M,
+            date(2024, 12, 20)..=date(2024, 12, 27),
+            cme,
+        )?;
+
+        // Validate: Fri 20, Mon 23, Tue 24, Thu 26, Fri 27
+        assert_eq!(params.len(), 5);
+        assert_eq!(
+            params[1].start_ts,
+            date_to_unix_nanos("2024-12-22 17:00 America/Chicago")?
+        );
+        assert!(params
+            .iter()
+            .all(|p| p.schema == Schema::Ohlcv1M.to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_timezone() {
+        let contents =
+            r#"{"X": {"timezone": "Nowhere/City", "open": "09:00:00", "close": "17:00:00"}}"#;
+
+        // Validate
+        assert!(Calendars::from_json(contents).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use crate::historical::RetrieveParams;
+use crate::stream::{read_metadata, write_metadata};
+use crate::utils::write_atomic;
+use crate::{error::Error, error::Result};
+use mbn::enums::Schema;
+use mbn::metadata::Metadata;
+use mbn::records::{BboMsg, Mbp1Msg, OhlcvMsg, TradeMsg};
+use mbn::symbols::SymbolMap;
+use serde::{Deserialize, Serialize};
+use std::mem;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::fs;
+
+const NANOS_PER_HOUR: i64 = 3_600_000_000_000;
+const NANOS_PER_DAY: i64 = 24 * NANOS_PER_HOUR;
+
+/// How a retrieval is split into time windows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkBy {
+    /// UTC calendar days.
+    Day,
+    /// UTC clock hours.
+    Hour,
+    /// Fixed length windows starting at `start_ts`.
+    Span(Duration),
+    /// Windows expected to return about `max_bytes`, given the number of
+    /// records per second expected for each symbol.
+    EstimatedSize {
+        max_bytes: u64,
+        records_per_second: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkOptions {
+    pub chunk_by: ChunkBy,
+    /// Maximum number of windows requested at once.
+    pub concurrency: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        ChunkOptions {
+            chunk_by: ChunkBy::Day,
+            concurrency: 4,
+        }
+    }
+}
+
+impl ChunkOptions {
+    pub fn new(chunk_by: ChunkBy, concurrency: usize) -> Self {
+        ChunkOptions {
+            chunk_by,
+            concurrency: concurrency.max(1),
+        }
+    }
+}
+
+pub(crate) fn parse_schema(schema: &str) -> Result<Schema> {
+    Schema::from_str(schema).map_err(|_| Error::CustomError(format!("Unknown schema '{}'", schema)))
+}
+
+/// Encoded size of a single record of `schema`.
+pub(crate) fn record_size(schema: Schema) -> usize {
+    match schema {
+        Schema::Mbp1 | Schema::Tbbo => mem::size_of::<Mbp1Msg>(),
+        Schema::Trade => mem::size_of::<TradeMsg>(),
+        Schema::Bbo1S | Schema::Bbo1M => mem::size_of::<BboMsg>(),
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+            mem::size_of::<OhlcvMsg>()
+        }
+    }
+}
+
+/// Splits `[start_ts, end_ts)` of `params` into consecutive windows.
+pub fn windows(params: &RetrieveParams, chunk_by: ChunkBy) -> Result<Vec<(i64, i64)>> {
+    let (start, end) = (params.start_ts, params.end_ts);
+
+    match chunk_by {
+        ChunkBy::Day => Ok(aligned_windows(start, end, NANOS_PER_DAY)),
+        ChunkBy::Hour => Ok(aligned_windows(start, end, NANOS_PER_HOUR)),
+        ChunkBy::Span(span) => fixed_windows(start, end, span.as_nanos() as i64),
+        ChunkBy::EstimatedSize {
+            max_bytes,
+            records_per_second,
+        } => {
+            if records_per_second <= 0.0 {
+                return Err(Error::CustomError(
+                    "Records per second must be positive.".to_string(),
+                ));
+            }
+
+            let schema = parse_schema(&params.schema)?;
+            let symbols = params.symbols.len().max(1) as f64;
+            let bytes_per_second = record_size(schema) as f64 * records_per_second * symbols;
+            let span = (max_bytes as f64 / bytes_per_second * 1e9) as i64;
+            fixed_windows(start, end, span)
+        }
+    }
+}
+
+/// Windows ending on multiples of `step` since the epoch.
+fn aligned_windows(start: i64, end: i64, step: i64) -> Vec<(i64, i64)> {
+    let mut windows = Vec::new();
+    let mut current = start;
+
+    while current < end {
+        let boundary = (current.div_euclid(step) + 1) * step;
+        let next = boundary.min(end);
+        windows.push((current, next));
+        current = next;
+    }
+    windows
+}
+
+fn fixed_windows(start: i64, end: i64, step: i64) -> Result<Vec<(i64, i64)>> {
+    if step <= 0 {
+        return Err(Error::CustomError(
+            "Chunk window must be at least 1 nanosecond.".to_string(),
+        ));
+    }
+
+    let mut windows = Vec::new();
+    let mut current = start;
+
+    while current < end {
+        let next = current.saturating_add(step).min(end);
+        windows.push((current, next));
+        current = next;
+    }
+    Ok(windows)
+}
+
+/// Combines per-window responses under a single metadata header covering the
+/// full requested range.
+#[derive(Debug)]
+pub(crate) struct Stitcher {
+    schema: String,
+    start: i64,
+    end: i64,
+    metadata: Option<Metadata>,
+}
+
+impl Stitcher {
+    pub(crate) fn new(params: &RetrieveParams) -> Self {
+        Stitcher {
+            schema: params.schema.clone(),
+            start: params.start_ts,
+            end: params.end_ts,
+            metadata: None,
+        }
+    }
+
+    /// Merges the metadata of a window response and returns its record bytes.
+    pub(crate) fn add<'a>(&mut self, data: &'a [u8]) -> Result<&'a [u8]> {
+        if data.is_empty() {
+            return Ok(data);
+        }
+
+        let (metadata, consumed) = read_metadata(data)?
+            .ok_or_else(|| Error::DecodeError("Incomplete metadata in response.".to_string()))?;
+
+        match &mut self.metadata {
+            Some(current) => current.mappings.map.extend(metadata.mappings.map),
+            None => self.metadata = Some(metadata),
+        }
+        Ok(&data[consumed..])
+    }
+
+    /// Encoded metadata for the combined range.
+    pub(crate) fn header(&self) -> Result<Vec<u8>> {
+        let mut metadata = match &self.metadata {
+            Some(metadata) => metadata.clone(),
+            None => Metadata::new(parse_schema(&self.schema)?, 0, 0, SymbolMap::new()),
+        };
+        metadata.start = self.start as u64;
+        metadata.end = self.end as u64;
+
+        Ok(write_metadata(&metadata))
+    }
+
+    /// Encoded metadata merged so far, restored later through `add`.
+    pub(crate) fn metadata_bytes(&self) -> Option<Vec<u8>> {
+        self.metadata.as_ref().map(write_metadata)
+    }
+}
+
+/// Progress of a resumable download, saved beside the output file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    pub params: RetrieveParams,
+    /// End of the last window fully written.
+    pub completed_until: i64,
+    /// Record bytes written up to `completed_until`.
+    pub offset: u64,
+    /// Metadata merged from the completed windows.
+    pub metadata: Option<Vec<u8>>,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(params: &RetrieveParams) -> Self {
+        Checkpoint {
+            params: params.clone(),
+            completed_until: params.start_ts,
+            offset: 0,
+            metadata: None,
+        }
+    }
+
+    pub(crate) async fn load(path: &str) -> Result<Option<Self>> {
+        match fs::read(path).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replaces the checkpoint atomically, so a crash never leaves a partial one.
+    pub(crate) async fn save(&self, path: &str) -> Result<()> {
+        write_atomic(path, &serde_json::to_vec(self)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use crate::stream::tests::{encoded, mbp, params};
+    use crate::stream::RecordBuffer;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    #[test]
+    fn test_day_windows_aligned() -> Result<()> {
+        let start = NANOS_PER_DAY + 5 * NANOS_PER_HOUR;
+        let end = 3 * NANOS_PER_DAY + NANOS_PER_HOUR;
+
+        // Test
+        let windows = windows(&params(start, end), ChunkBy::Day)?;
+
+        // Validate
+        assert_eq!(
+            windows,
+            vec![
+                (start, 2 * NANOS_PER_DAY),
+                (2 * NANOS_PER_DAY, 3 * NANOS_PER_DAY),
+                (3 * NANOS_PER_DAY, end),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_span_windows() -> Result<()> {
+        // Test
+        let windows = windows(&params(0, 25), ChunkBy::Span(Duration::from_nanos(10)))?;
+
+        // Validate
+        assert_eq!(windows, vec![(0, 10), (10, 20), (20, 25)]);
+        assert!(super::windows(&params(0, 25), ChunkBy::Span(Duration::ZERO)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimated_size_windows() -> Result<()> {
+        let size = record_size(Schema::Mbp1) as u64;
+        let chunk_by = ChunkBy::EstimatedSize {
+            max_bytes: size * 10,
+            records_per_second: 1.0,
+        };
+
+        // Test
+        let windows = windows(&params(0, 30_000_000_000), chunk_by)?;
+
+        // Validate
+        assert_eq!(
+            windows,
+            vec![
+                (0, 10_000_000_000),
+                (10_000_000_000, 20_000_000_000),
+                (20_000_000_000, 30_000_000_000)
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_range() -> Result<()> {
+        // Validate
+        assert!(windows(&params(10, 10), ChunkBy::Hour)?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_records_chunked() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _first = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 0, "end_ts": 10})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 1), mbp(1, 2)]))
+            .create_async()
+            .await;
+        let _second = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 10, "end_ts": 20})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 11)]))
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let options = ChunkOptions::new(ChunkBy::Span(Duration::from_nanos(10)), 2);
+
+        // Test
+        let response = client.get_records_chunked(&params(0, 20), &options).await?;
+
+        // Validate
+        let mut buffer = RecordBuffer::new();
+        buffer.push(&response.data);
+        let mut timestamps = Vec::new();
+        while let Some(record) = buffer.next_record_bytes()? {
+            timestamps.push(crate::stream::read_header(record).unwrap().ts_event);
+        }
+        let metadata = buffer.metadata().expect("Missing metadata");
+        assert_eq!(timestamps, vec![1, 2, 11]);
+        assert_eq!((metadata.start, metadata.end), (0, 20));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resumable_download() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 0, "end_ts": 10})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 1), mbp(1, 2)]))
+            .expect(1)
+            .create_async()
+            .await;
+        let failing = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 10, "end_ts": 20})))
+            .with_status(500)
+            .with_body(json!({"status": "failed", "message": "Crashed", "code": 500}).to_string())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let options = ChunkOptions::new(ChunkBy::Span(Duration::from_nanos(10)), 1);
+        let path = std::env::temp_dir().join("midas_client_resumable_test.bin");
+        let path = path.to_str().unwrap();
+        let checkpoint_path = format!("{}.checkpoint", path);
+
+        // Test
+        let result = client
+            .get_records_to_file_resumable(&params(0, 20), &options, path)
+            .await;
+
+        // Validate
+        assert!(matches!(result, Err(Error::ServerError { .. })));
+        let checkpoint = Checkpoint::load(&checkpoint_path).await?.unwrap();
+        assert_eq!(checkpoint.completed_until, 10);
+        assert_eq!(checkpoint.offset, 2 * record_size(Schema::Mbp1) as u64);
+
+        // Test
+        failing.remove_async().await;
+        let _second = server
+            .mock("GET", "/historical/mbp/get")
+            .match_body(Matcher::PartialJson(json!({"start_ts": 10, "end_ts": 20})))
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 11)]))
+            .create_async()
+            .await;
+        client
+            .get_records_to_file_resumable(&params(0, 20), &options, path)
+            .await?;
+
+        // Validate
+        let mut buffer = RecordBuffer::new();
+        buffer.push(&tokio::fs::read(path).await?);
+        let mut timestamps = Vec::new();
+        while let Some(record) = buffer.next_record_bytes()? {
+            timestamps.push(crate::stream::read_header(record).unwrap().ts_event);
+        }
+        assert_eq!(timestamps, vec![1, 2, 11]);
+        assert!(!std::path::Path::new(&checkpoint_path).exists());
+        first.assert_async().await;
+
+        // Cleanup
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+}
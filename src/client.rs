@@ -0,0 +1,243 @@
+use crate::auth::Auth;
+use crate::historical::Historical;
+use crate::retry::RetryPolicy;
+use crate::trading::Trading;
+use crate::{error::Error, error::Result};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, ClientBuilder};
+use std::time::Duration;
+
+/// Request timeout used when none is configured.
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(20000);
+
+/// Top-level client handing out `Historical` and `Trading` handles that share
+/// one connection pool and configuration.
+#[derive(Clone)]
+pub struct MidasClient {
+    historical_url: String,
+    trading_url: String,
+    client: Client,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+}
+
+impl MidasClient {
+    pub fn builder() -> MidasClientBuilder {
+        MidasClientBuilder::default()
+    }
+
+    pub fn historical(&self) -> Historical {
+        let historical = Historical::with_client(&self.historical_url, self.client.clone())
+            .with_retry(self.retry.clone());
+        match &self.auth {
+            Some(auth) => historical.with_auth(auth.clone()),
+            None => historical,
+        }
+    }
+
+    pub fn trading(&self) -> Trading {
+        let trading = Trading::with_client(&self.trading_url, self.client.clone())
+            .with_retry(self.retry.clone());
+        match &self.auth {
+            Some(auth) => trading.with_auth(auth.clone()),
+            None => trading,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MidasClientBuilder {
+    base_url: Option<String>,
+    historical_url: Option<String>,
+    trading_url: Option<String>,
+    timeout: Duration,
+    connect_timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    user_agent: Option<String>,
+    default_headers: HeaderMap,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+}
+
+impl Default for MidasClientBuilder {
+    fn default() -> Self {
+        MidasClientBuilder {
+            base_url: None,
+            historical_url: None,
+            trading_url: None,
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: None,
+            pool_max_idle_per_host: None,
+            user_agent: None,
+            default_headers: HeaderMap::new(),
+            auth: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+impl MidasClientBuilder {
+    /// Base URL used for both APIs unless overridden individually.
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.base_url = Some(url.to_string());
+        self
+    }
+
+    pub fn historical_url(mut self, url: &str) -> Self {
+        self.historical_url = Some(url.to_string());
+        self
+    }
+
+    pub fn trading_url(mut self, url: &str) -> Self {
+        self.trading_url = Some(url.to_string());
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum idle connections kept per host in the shared pool.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Header sent with every request, replacing any previous value for `name`.
+    pub fn default_header(mut self, name: &str, value: &str) -> Result<Self> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| Error::CustomError(format!("Invalid header name '{}': {}", name, e)))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| Error::CustomError(format!("Invalid header value: {}", e)))?;
+        self.default_headers.insert(name, value);
+        Ok(self)
+    }
+
+    pub fn default_headers(mut self, headers: HeaderMap) -> Self {
+        self.default_headers.extend(headers);
+        self
+    }
+
+    /// Credentials applied to every historical and trading request.
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Retry policy shared by the historical and trading handles.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn build(self) -> Result<MidasClient> {
+        let historical_url = self
+            .historical_url
+            .or_else(|| self.base_url.clone())
+            .ok_or_else(|| Error::CustomError("Missing historical base url.".to_string()))?;
+        let trading_url = self
+            .trading_url
+            .or_else(|| self.base_url.clone())
+            .ok_or_else(|| Error::CustomError("Missing trading base url.".to_string()))?;
+
+        let mut builder = ClientBuilder::new()
+            .timeout(self.timeout)
+            .default_headers(self.default_headers);
+
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(user_agent) = self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+
+        Ok(MidasClient {
+            historical_url,
+            trading_url,
+            client: builder.build()?,
+            auth: self.auth,
+            retry: self.retry,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_urls() -> Result<()> {
+        let client = MidasClient::builder()
+            .base_url("http://127.0.0.1:8080")
+            .trading_url("http://127.0.0.1:9090")
+            .build()?;
+
+        // Validate
+        assert_eq!(client.historical_url, "http://127.0.0.1:8080");
+        assert_eq!(client.trading_url, "http://127.0.0.1:9090");
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_missing_url() {
+        let result = MidasClient::builder()
+            .historical_url("http://127.0.0.1:8080")
+            .build();
+
+        // Validate
+        assert!(matches!(result, Err(Error::CustomError(_))));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let result = MidasClient::builder().default_header("bad header", "value");
+
+        // Validate
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shared_config_applied() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/historical/instruments/list")
+            .match_header("user-agent", "midas-test")
+            .match_header("x-desk", "research")
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": []}).to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = MidasClient::builder()
+            .base_url(&server.url())
+            .user_agent("midas-test")
+            .default_header("x-desk", "research")?
+            .connect_timeout(Duration::from_secs(5))
+            .build()?;
+
+        // Test
+        let response = client.historical().list_symbols().await?;
+
+        // Validate
+        assert_eq!(response.status, "success");
+        mock.assert_async().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,480 @@
+use crate::chunk::parse_schema;
+use crate::export::{character, price, symbol};
+use crate::stream::{RecordBuffer, RecordStream};
+use crate::{error::Error, error::Result};
+use arrow::array::{
+    ArrayRef, Float64Builder, StringBuilder, TimestampNanosecondBuilder, UInt32Builder,
+    UInt64Builder, UInt8Builder,
+};
+use arrow::datatypes::{DataType, Field, Schema as ArrowSchema, SchemaRef, TimeUnit};
+use arrow::record_batch::RecordBatch;
+use futures_util::StreamExt;
+use mbn::enums::Schema;
+use mbn::record_enum::RecordEnum;
+use mbn::records::{BidAskPair, RecordHeader};
+use mbn::symbols::SymbolMap;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+use tokio::fs;
+
+/// Rows per batch when no size is given.
+pub const DEFAULT_BATCH_SIZE: usize = 65_536;
+
+fn timestamp(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        false,
+    )
+}
+
+fn is_ohlcv(schema: Schema) -> bool {
+    matches!(
+        schema,
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D
+    )
+}
+
+fn header_fields(received: bool) -> Vec<Field> {
+    let mut fields = vec![timestamp("ts_event")];
+    if received {
+        fields.push(timestamp("ts_recv"));
+    }
+    fields.push(Field::new("symbol", DataType::Utf8, false));
+    fields.push(Field::new("instrument_id", DataType::UInt32, false));
+    fields
+}
+
+fn book_fields() -> Vec<Field> {
+    vec![
+        Field::new("bid_px", DataType::Float64, false),
+        Field::new("ask_px", DataType::Float64, false),
+        Field::new("bid_sz", DataType::UInt32, false),
+        Field::new("ask_sz", DataType::UInt32, false),
+        Field::new("bid_ct", DataType::UInt32, false),
+        Field::new("ask_ct", DataType::UInt32, false),
+    ]
+}
+
+/// Arrow schema of the rows exported for `schema`, with the same columns as
+/// the CSV export but typed timestamps.
+pub fn arrow_schema(schema: Schema) -> SchemaRef {
+    let mut fields = header_fields(!is_ohlcv(schema));
+
+    match schema {
+        Schema::Mbp1 | Schema::Tbbo | Schema::Trade => {
+            fields.extend([
+                Field::new("action", DataType::Utf8, false),
+                Field::new("side", DataType::Utf8, false),
+                Field::new("depth", DataType::UInt8, false),
+                Field::new("price", DataType::Float64, false),
+                Field::new("size", DataType::UInt32, false),
+                Field::new("flags", DataType::UInt8, false),
+                Field::new("sequence", DataType::UInt32, false),
+            ]);
+            if schema != Schema::Trade {
+                fields.extend(book_fields());
+            }
+        }
+        Schema::Bbo1S | Schema::Bbo1M => {
+            fields.extend([
+                Field::new("side", DataType::Utf8, false),
+                Field::new("price", DataType::Float64, false),
+                Field::new("size", DataType::UInt32, false),
+                Field::new("flags", DataType::UInt8, false),
+                Field::new("sequence", DataType::UInt32, false),
+            ]);
+            fields.extend(book_fields());
+        }
+        Schema::Ohlcv1S | Schema::Ohlcv1M | Schema::Ohlcv1H | Schema::Ohlcv1D => {
+            fields.extend([
+                Field::new("open", DataType::Float64, false),
+                Field::new("high", DataType::Float64, false),
+                Field::new("low", DataType::Float64, false),
+                Field::new("close", DataType::Float64, false),
+                Field::new("volume", DataType::UInt64, false),
+            ]);
+        }
+    }
+
+    Arc::new(ArrowSchema::new(fields))
+}
+
+/// Whether `record` is the record type returned for `schema`.
+fn matches_schema(schema: Schema, record: &RecordEnum) -> bool {
+    match record {
+        RecordEnum::Mbp1(_) | RecordEnum::Tbbo(_) => matches!(schema, Schema::Mbp1 | Schema::Tbbo),
+        RecordEnum::Trade(_) => schema == Schema::Trade,
+        RecordEnum::Bbo(_) => matches!(schema, Schema::Bbo1S | Schema::Bbo1M),
+        RecordEnum::Ohlcv(_) => is_ohlcv(schema),
+    }
+}
+
+/// Builders for every exported column, of which a batch takes the ones in
+/// its schema.
+#[derive(Default)]
+struct Columns {
+    ts_event: TimestampNanosecondBuilder,
+    ts_recv: TimestampNanosecondBuilder,
+    symbol: StringBuilder,
+    instrument_id: UInt32Builder,
+    action: StringBuilder,
+    side: StringBuilder,
+    depth: UInt8Builder,
+    price: Float64Builder,
+    size: UInt32Builder,
+    flags: UInt8Builder,
+    sequence: UInt32Builder,
+    bid_px: Float64Builder,
+    ask_px: Float64Builder,
+    bid_sz: UInt32Builder,
+    ask_sz: UInt32Builder,
+    bid_ct: UInt32Builder,
+    ask_ct: UInt32Builder,
+    open: Float64Builder,
+    high: Float64Builder,
+    low: Float64Builder,
+    close: Float64Builder,
+    volume: UInt64Builder,
+}
+
+impl Columns {
+    fn header(&mut self, header: &RecordHeader, mappings: Option<&SymbolMap>) {
+        self.ts_event.append_value(header.ts_event as i64);
+        self.symbol.append_value(symbol(mappings, header));
+        self.instrument_id.append_value(header.instrument_id);
+    }
+
+    fn book(&mut self, level: &BidAskPair) {
+        self.bid_px.append_value(price(level.bid_px));
+        self.ask_px.append_value(price(level.ask_px));
+        self.bid_sz.append_value(level.bid_sz);
+        self.ask_sz.append_value(level.ask_sz);
+        self.bid_ct.append_value(level.bid_ct);
+        self.ask_ct.append_value(level.ask_ct);
+    }
+
+    fn append(&mut self, record: &RecordEnum, mappings: Option<&SymbolMap>) {
+        match record {
+            RecordEnum::Mbp1(msg) | RecordEnum::Tbbo(msg) => {
+                self.header(&msg.hd, mappings);
+                self.ts_recv.append_value(msg.ts_recv as i64);
+                self.action.append_value(character(msg.action));
+                self.side.append_value(character(msg.side));
+                self.depth.append_value(msg.depth);
+                self.price.append_value(price(msg.price));
+                self.size.append_value(msg.size);
+                self.flags.append_value(msg.flags);
+                self.sequence.append_value(msg.sequence);
+                self.book(&msg.levels[0]);
+            }
+            RecordEnum::Trade(msg) => {
+                self.header(&msg.hd, mappings);
+                self.ts_recv.append_value(msg.ts_recv as i64);
+                self.action.append_value(character(msg.action));
+                self.side.append_value(character(msg.side));
+                self.depth.append_value(msg.depth);
+                self.price.append_value(price(msg.price));
+                self.size.append_value(msg.size);
+                self.flags.append_value(msg.flags);
+                self.sequence.append_value(msg.sequence);
+            }
+            RecordEnum::Bbo(msg) => {
+                self.header(&msg.hd, mappings);
+                self.ts_recv.append_value(msg.ts_recv as i64);
+                self.side.append_value(character(msg.side));
+                self.price.append_value(price(msg.price));
+                self.size.append_value(msg.size);
+                self.flags.append_value(msg.flags);
+                self.sequence.append_value(msg.sequence);
+                self.book(&msg.levels[0]);
+            }
+            RecordEnum::Ohlcv(msg) => {
+                self.header(&msg.hd, mappings);
+                self.open.append_value(price(msg.open));
+                self.high.append_value(price(msg.high));
+                self.low.append_value(price(msg.low));
+                self.close.append_value(price(msg.close));
+                self.volume.append_value(msg.volume);
+            }
+        }
+    }
+
+    /// Finishes the builder of the column named `name`.
+    fn finish(&mut self, name: &str) -> Result<ArrayRef> {
+        let column: ArrayRef = match name {
+            "ts_event" => Arc::new(self.ts_event.finish().with_timezone("UTC")),
+            "ts_recv" => Arc::new(self.ts_recv.finish().with_timezone("UTC")),
+            "symbol" => Arc::new(self.symbol.finish()),
+            "instrument_id" => Arc::new(self.instrument_id.finish()),
+            "action" => Arc::new(self.action.finish()),
+            "side" => Arc::new(self.side.finish()),
+            "depth" => Arc::new(self.depth.finish()),
+            "price" => Arc::new(self.price.finish()),
+            "size" => Arc::new(self.size.finish()),
+            "flags" => Arc::new(self.flags.finish()),
+            "sequence" => Arc::new(self.sequence.finish()),
+            "bid_px" => Arc::new(self.bid_px.finish()),
+            "ask_px" => Arc::new(self.ask_px.finish()),
+            "bid_sz" => Arc::new(self.bid_sz.finish()),
+            "ask_sz" => Arc::new(self.ask_sz.finish()),
+            "bid_ct" => Arc::new(self.bid_ct.finish()),
+            "ask_ct" => Arc::new(self.ask_ct.finish()),
+            "open" => Arc::new(self.open.finish()),
+            "high" => Arc::new(self.high.finish()),
+            "low" => Arc::new(self.low.finish()),
+            "close" => Arc::new(self.close.finish()),
+            "volume" => Arc::new(self.volume.finish()),
+            _ => return Err(Error::CustomError(format!("Unknown column '{}'", name))),
+        };
+        Ok(column)
+    }
+}
+
+/// Collects records of a single schema into `RecordBatch`es of up to
+/// `batch_size` rows.
+pub struct BatchBuilder {
+    record_schema: Schema,
+    schema: SchemaRef,
+    columns: Columns,
+    rows: usize,
+    batch_size: usize,
+    mappings: Option<SymbolMap>,
+}
+
+impl BatchBuilder {
+    pub fn new(schema: Schema, batch_size: usize) -> Result<Self> {
+        Ok(BatchBuilder {
+            record_schema: schema,
+            schema: arrow_schema(schema),
+            columns: Columns::default(),
+            rows: 0,
+            batch_size: batch_size.max(1),
+            mappings: None,
+        })
+    }
+
+    /// Mappings used to fill in the `symbol` column.
+    pub fn with_mappings(mut self, mappings: SymbolMap) -> Self {
+        self.mappings = Some(mappings);
+        self
+    }
+
+    pub fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    /// Adds a record, returning a batch once `batch_size` rows are collected.
+    pub fn push(&mut self, record: &RecordEnum) -> Result<Option<RecordBatch>> {
+        if !matches_schema(self.record_schema, record) {
+            return Err(Error::DecodeError(format!(
+                "Record doesn't match schema {}",
+                self.record_schema
+            )));
+        }
+        self.columns.append(record, self.mappings.as_ref());
+        self.rows += 1;
+
+        if self.rows < self.batch_size {
+            return Ok(None);
+        }
+        self.flush()
+    }
+
+    /// Returns the rows collected so far as a batch, `None` if there are none.
+    pub fn flush(&mut self) -> Result<Option<RecordBatch>> {
+        if self.rows == 0 {
+            return Ok(None);
+        }
+
+        let columns = self
+            .schema
+            .fields()
+            .iter()
+            .map(|field| self.columns.finish(field.name()))
+            .collect::<Result<Vec<_>>>()?;
+        self.rows = 0;
+        Ok(Some(RecordBatch::try_new(self.schema.clone(), columns)?))
+    }
+}
+
+/// Converts an mbn buffer such as the data returned by `get_records` into
+/// batches of up to `batch_size` rows.
+pub fn record_batches(data: &[u8], batch_size: usize) -> Result<Vec<RecordBatch>> {
+    let mut buffer = RecordBuffer::new();
+    buffer.push(data);
+
+    // Reads the metadata, which carries the schema of the records
+    let record = buffer.next_record()?;
+    let metadata = buffer
+        .metadata()
+        .ok_or_else(|| Error::DecodeError("Missing metadata".to_string()))?;
+    let mut builder =
+        BatchBuilder::new(metadata.schema, batch_size)?.with_mappings(metadata.mappings.clone());
+
+    let mut batches = Vec::new();
+    let mut next = record;
+    while let Some(record) = next {
+        if let Some(batch) = builder.push(&record)? {
+            batches.push(batch);
+        }
+        next = buffer.next_record()?;
+    }
+    buffer.finish()?;
+
+    if let Some(batch) = builder.flush()? {
+        batches.push(batch);
+    }
+    Ok(batches)
+}
+
+/// Settings for `get_records_to_parquet`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParquetOptions {
+    /// Rows per row group, also the number of rows held in memory at once.
+    pub row_group_size: usize,
+    pub compression: Compression,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> Self {
+        ParquetOptions {
+            row_group_size: DEFAULT_BATCH_SIZE,
+            compression: Compression::SNAPPY,
+        }
+    }
+}
+
+impl ParquetOptions {
+    pub fn new(row_group_size: usize, compression: Compression) -> Self {
+        ParquetOptions {
+            row_group_size: row_group_size.max(1),
+            compression,
+        }
+    }
+}
+
+/// Writes records from `stream_records` to a Parquet file at `path` one row
+/// group at a time, returning the number of records written.
+pub(crate) async fn write_parquet(
+    mut stream: RecordStream,
+    schema: &str,
+    path: &str,
+    options: &ParquetOptions,
+) -> Result<u64> {
+    let mut builder = BatchBuilder::new(parse_schema(schema)?, options.row_group_size)?;
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(options.row_group_size.max(1))
+        .set_compression(options.compression)
+        .build();
+    let file = fs::File::create(path).await?;
+    let mut writer = AsyncArrowWriter::try_new(file, builder.schema(), Some(properties))?;
+    let mut records = 0;
+
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        if builder.mappings.is_none() {
+            builder.mappings = stream.metadata().map(|m| m.mappings.clone());
+        }
+        if let Some(batch) = builder.push(&record)? {
+            writer.write(&batch).await?;
+        }
+        records += 1;
+    }
+
+    if let Some(batch) = builder.flush()? {
+        writer.write(&batch).await?;
+    }
+    writer.close().await?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::{Historical, RetrieveParams};
+    use crate::stream::tests::{encoded, mbp};
+    use arrow::array::{Array, Float64Array, StringArray, TimestampNanosecondArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_record_batches() -> Result<()> {
+        let data = encoded(&[mbp(1, 1), mbp(1, 2), mbp(1, 3)]);
+
+        // Test
+        let batches = record_batches(&data, 2)?;
+
+        // Validate
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].num_rows(), 2);
+        assert_eq!(batches[1].num_rows(), 1);
+        assert_eq!(batches[0].schema(), arrow_schema(Schema::Mbp1));
+
+        let batch = &batches[0];
+        let ts = batch
+            .column_by_name("ts_event")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<TimestampNanosecondArray>()
+            .unwrap();
+        let symbol = batch
+            .column_by_name("symbol")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .unwrap();
+        let price = batch
+            .column_by_name("price")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(ts.value(1), 2);
+        assert_eq!(symbol.value(0), "AAPL9");
+        assert_eq!(price.value(0), 6.77e-6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_records_to_parquet() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 1), mbp(1, 2), mbp(1, 3)]))
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let params = RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts: 0,
+            end_ts: 10,
+            schema: Schema::Mbp1.to_string(),
+        };
+        let path = std::env::temp_dir().join("midas_test_get_records.parquet");
+        let path = path.to_str().unwrap();
+
+        // Test
+        let options = ParquetOptions::new(2, Compression::UNCOMPRESSED);
+        let records = client
+            .get_records_to_parquet(&params, path, &options)
+            .await?;
+
+        // Validate
+        let file = std::fs::File::open(path)?;
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        assert_eq!(reader.metadata().num_row_groups(), 2);
+        let rows: usize = reader
+            .build()?
+            .map(|batch| batch.map(|b| b.num_rows()))
+            .sum::<std::result::Result<usize, arrow::error::ArrowError>>()?;
+        assert_eq!(records, 3);
+        assert_eq!(rows, 3);
+
+        // Cleanup
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+}
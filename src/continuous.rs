@@ -0,0 +1,485 @@
+use crate::historical::RetrieveParams;
+use crate::utils::IntoUnixNanos;
+use crate::{error::Error, error::Result};
+use chrono::{Days, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How a continuous symbol picks the contract it follows.
+///
+/// `.n.` and `.v.` roll on the observed `Contract::roll_date`. For contracts
+/// without one they fall back to the calendar roll, so a table without
+/// observed dates resolves every rule the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RollRule {
+    /// `.c.`, rolls a fixed number of days before expiration.
+    Calendar,
+    /// `.n.`, rolls when open interest moves to the next contract.
+    OpenInterest,
+    /// `.v.`, rolls when volume moves to the next contract.
+    Volume,
+}
+
+impl RollRule {
+    fn code(&self) -> &'static str {
+        match self {
+            RollRule::Calendar => "c",
+            RollRule::OpenInterest => "n",
+            RollRule::Volume => "v",
+        }
+    }
+}
+
+/// A continuous contract symbol such as `HE.n.0`, where the rank counts
+/// contracts out from the front month.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContinuousSymbol {
+    pub root: String,
+    pub rule: RollRule,
+    pub rank: usize,
+}
+
+impl ContinuousSymbol {
+    /// Whether `symbol` uses continuous symbology rather than naming an outright.
+    pub fn is_continuous(symbol: &str) -> bool {
+        symbol.parse::<ContinuousSymbol>().is_ok()
+    }
+}
+
+impl FromStr for ContinuousSymbol {
+    type Err = Error;
+
+    fn from_str(symbol: &str) -> Result<Self> {
+        let invalid = || {
+            Error::Validation(format!(
+                "'{}' is not a continuous symbol, expected ROOT.[c|n|v].RANK",
+                symbol
+            ))
+        };
+
+        let mut parts = symbol.rsplitn(3, '.');
+        let rank = parts.next().ok_or_else(invalid)?;
+        let rule = parts.next().ok_or_else(invalid)?;
+        let root = parts.next().filter(|r| !r.is_empty()).ok_or_else(invalid)?;
+
+        let rule = match rule {
+            "c" => RollRule::Calendar,
+            "n" => RollRule::OpenInterest,
+            "v" => RollRule::Volume,
+            _ => return Err(invalid()),
+        };
+        let rank = rank.parse().map_err(|_| invalid())?;
+
+        Ok(ContinuousSymbol {
+            root: root.to_string(),
+            rule,
+            rank,
+        })
+    }
+}
+
+impl fmt::Display for ContinuousSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.root, self.rule.code(), self.rank)
+    }
+}
+
+/// One outright contract of a root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contract {
+    /// Outright symbol, e.g. `HEG4`.
+    pub symbol: String,
+    pub expiration: NaiveDate,
+    /// Observed date open interest or volume moved off this contract, used by
+    /// the `.n.` and `.v.` rules. Those rules fall back to the calendar roll
+    /// when it isn't set.
+    #[serde(default)]
+    pub roll_date: Option<NaiveDate>,
+}
+
+/// Contract `symbol` held over `[start, end)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollPeriod {
+    pub symbol: String,
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// Outright contracts keyed by root, e.g. `HE`, used to resolve continuous
+/// symbols without asking the server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExpiryTable {
+    roots: HashMap<String, Vec<Contract>>,
+}
+
+impl ExpiryTable {
+    /// Loads a `.toml` or `.json` file with one array of contracts per root.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&contents),
+            Some("json") => Self::from_json(&contents),
+            _ => Err(Error::CustomError(format!(
+                "Unsupported expiry file '{}', expected .toml or .json",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let table: ExpiryTable = toml::from_str(contents)?;
+        table.sorted()
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let table: ExpiryTable = serde_json::from_str(contents)?;
+        table.sorted()
+    }
+
+    fn sorted(mut self) -> Result<Self> {
+        for (root, contracts) in self.roots.iter_mut() {
+            contracts.sort_by_key(|c| c.expiration);
+            check_order(root, contracts)?;
+        }
+        Ok(self)
+    }
+
+    /// Adds a contract to `root`, leaving the table unchanged if it would
+    /// break the order of its expirations or observed roll dates.
+    pub fn insert(&mut self, root: &str, contract: Contract) -> Result<()> {
+        let mut contracts = self.contracts(root).to_vec();
+        contracts.push(contract);
+        contracts.sort_by_key(|c| c.expiration);
+        check_order(root, &contracts)?;
+
+        self.roots.insert(root.to_string(), contracts);
+        Ok(())
+    }
+
+    /// Contracts of `root` by expiration.
+    pub fn contracts(&self, root: &str) -> &[Contract] {
+        self.roots.get(root).map_or(&[], Vec::as_slice)
+    }
+
+    /// Date the front month moves off `contract` under `rule`.
+    fn roll_date(contract: &Contract, rule: RollRule, days_before_expiry: u64) -> NaiveDate {
+        let calendar = contract
+            .expiration
+            .checked_sub_days(Days::new(days_before_expiry))
+            .unwrap_or(contract.expiration);
+
+        match rule {
+            RollRule::Calendar => calendar,
+            RollRule::OpenInterest | RollRule::Volume => contract.roll_date.unwrap_or(calendar),
+        }
+    }
+
+    /// Roll dates of `root` under `rule`, one per contract, rolling calendar
+    /// contracts `days_before_expiry` days before they expire.
+    pub fn roll_dates(
+        &self,
+        root: &str,
+        rule: RollRule,
+        days_before_expiry: u64,
+    ) -> Vec<NaiveDate> {
+        self.contracts(root)
+            .iter()
+            .map(|c| Self::roll_date(c, rule, days_before_expiry))
+            .collect()
+    }
+
+    /// Outright contracts `symbol` follows over `[start, end)`.
+    pub fn schedule(
+        &self,
+        symbol: &ContinuousSymbol,
+        start: NaiveDate,
+        end: NaiveDate,
+        days_before_expiry: u64,
+    ) -> Result<Vec<RollPeriod>> {
+        let contracts = self.contracts(&symbol.root);
+        let rolls = self.roll_dates(&symbol.root, symbol.rule, days_before_expiry);
+        // Observed dates mixed with calendar fallbacks can still go backwards
+        if let Some(pair) = rolls.windows(2).find(|pair| pair[0] >= pair[1]) {
+            return Err(Error::Validation(format!(
+                "Roll dates of {} don't increase under {}: {} then {}",
+                symbol.root, symbol, pair[0], pair[1]
+            )));
+        }
+        let mut periods = Vec::new();
+        let mut current = start;
+
+        while current < end {
+            // Front month is the first contract not yet rolled off
+            let front = rolls
+                .iter()
+                .position(|roll| *roll > current)
+                .ok_or_else(|| missing(symbol, current))?;
+            let contract = contracts
+                .get(front + symbol.rank)
+                .ok_or_else(|| missing(symbol, current))?;
+            let next = rolls[front].min(end);
+
+            periods.push(RollPeriod {
+                symbol: contract.symbol.clone(),
+                start: current,
+                end: next,
+            });
+            current = next;
+        }
+
+        Ok(periods)
+    }
+}
+
+/// Checks that `contracts`, sorted by expiration, expire on distinct dates and
+/// that their observed roll dates increase and fall on or before expiration.
+fn check_order(root: &str, contracts: &[Contract]) -> Result<()> {
+    let invalid = |message: String| Err(Error::Validation(format!("{}: {}", root, message)));
+
+    for pair in contracts.windows(2) {
+        if pair[0].expiration == pair[1].expiration {
+            return invalid(format!(
+                "{} and {} expire on the same date",
+                pair[0].symbol, pair[1].symbol
+            ));
+        }
+    }
+    for contract in contracts {
+        if contract
+            .roll_date
+            .is_some_and(|roll| roll > contract.expiration)
+        {
+            return invalid(format!("{} rolls after it expires", contract.symbol));
+        }
+    }
+
+    let observed: Vec<&Contract> = contracts.iter().filter(|c| c.roll_date.is_some()).collect();
+    for pair in observed.windows(2) {
+        if pair[0].roll_date >= pair[1].roll_date {
+            return invalid(format!(
+                "roll date of {} is not after the roll date of {}",
+                pair[1].symbol, pair[0].symbol
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn missing(symbol: &ContinuousSymbol, date: NaiveDate) -> Error {
+    Error::Validation(format!(
+        "No contract in the expiry table for {} on {}",
+        symbol, date
+    ))
+}
+
+/// Expands the continuous symbols in `params` into one request per
+/// outright contract each follows over the request's window, keeping the
+/// schema. Other symbols stay together in a request over the full window.
+pub fn params_for_continuous(
+    params: &RetrieveParams,
+    table: &ExpiryTable,
+    days_before_expiry: u64,
+) -> Result<Vec<RetrieveParams>> {
+    // Dates covering the window, the end rounded up to the next midnight
+    let start = Utc.timestamp_nanos(params.start_ts).date_naive();
+    let end = Utc.timestamp_nanos(params.end_ts - 1).date_naive() + Days::new(1);
+
+    let (continuous, outrights): (Vec<&String>, Vec<&String>) = params
+        .symbols
+        .iter()
+        .partition(|symbol| ContinuousSymbol::is_continuous(symbol));
+
+    let mut requests = Vec::new();
+    if !outrights.is_empty() {
+        let mut request = params.clone();
+        request.symbols = outrights.into_iter().cloned().collect();
+        requests.push(request);
+    }
+
+    for symbol in continuous {
+        let symbol: ContinuousSymbol = symbol.parse()?;
+        for period in table.schedule(&symbol, start, end, days_before_expiry)? {
+            let start_ts = period.start.into_unix_nanos()?.max(params.start_ts);
+            let end_ts = period.end.into_unix_nanos()?.min(params.end_ts);
+            if start_ts < end_ts {
+                let mut request = params.window(start_ts, end_ts);
+                request.symbols = vec![period.symbol];
+                requests.push(request);
+            }
+        }
+    }
+
+    Ok(requests)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mbn::enums::Schema;
+
+    const EXPIRIES: &str = r#"
+        [[HE]]
+        symbol = "HEG4"
+        expiration = "2024-02-14"
+
+        [[HE]]
+        symbol = "HEJ4"
+        expiration = "2024-04-12"
+        roll_date = "2024-03-28"
+
+        [[HE]]
+        symbol = "HEM4"
+        expiration = "2024-06-14"
+    "#;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn period(symbol: &str, start: NaiveDate, end: NaiveDate) -> RollPeriod {
+        RollPeriod {
+            symbol: symbol.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_parse_symbol() -> Result<()> {
+        // Test
+        let symbol: ContinuousSymbol = "HE.n.0".parse()?;
+
+        // Validate
+        assert_eq!(symbol.root, "HE");
+        assert_eq!(symbol.rule, RollRule::OpenInterest);
+        assert_eq!(symbol.rank, 0);
+        assert_eq!(symbol.to_string(), "HE.n.0");
+        assert_eq!("ES.FUT.c.1".parse::<ContinuousSymbol>()?.root, "ES.FUT");
+        assert!(ContinuousSymbol::is_continuous("ZC.v.2"));
+        assert!(!ContinuousSymbol::is_continuous("ZCH4"));
+        assert!(!ContinuousSymbol::is_continuous("ZC.x.0"));
+        assert!(!ContinuousSymbol::is_continuous(".n.0"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule() -> Result<()> {
+        let table = ExpiryTable::from_toml(EXPIRIES)?;
+        let symbol: ContinuousSymbol = "HE.n.0".parse()?;
+
+        // Test
+        let periods = table.schedule(&symbol, date(2024, 1, 1), date(2024, 5, 1), 2)?;
+
+        // Validate
+        assert_eq!(
+            periods,
+            vec![
+                period("HEG4", date(2024, 1, 1), date(2024, 2, 12)),
+                period("HEJ4", date(2024, 2, 12), date(2024, 3, 28)),
+                period("HEM4", date(2024, 3, 28), date(2024, 5, 1)),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_calendar_second_month() -> Result<()> {
+        let table = ExpiryTable::from_toml(EXPIRIES)?;
+        let symbol: ContinuousSymbol = "HE.c.1".parse()?;
+
+        // Test
+        let periods = table.schedule(&symbol, date(2024, 2, 1), date(2024, 3, 1), 0)?;
+        let beyond = table.schedule(&symbol, date(2024, 4, 12), date(2024, 5, 1), 0);
+
+        // Validate
+        assert_eq!(
+            periods,
+            vec![
+                period("HEJ4", date(2024, 2, 1), date(2024, 2, 14)),
+                period("HEM4", date(2024, 2, 14), date(2024, 3, 1)),
+            ]
+        );
+        assert!(matches!(beyond, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expiry_table_order() -> Result<()> {
+        let mut table = ExpiryTable::from_toml(EXPIRIES)?;
+        let backwards = r#"
+            [[HE]]
+            symbol = "HEG4"
+            expiration = "2024-02-14"
+            roll_date = "2024-02-01"
+
+            [[HE]]
+            symbol = "HEJ4"
+            expiration = "2024-04-12"
+            roll_date = "2024-01-30"
+        "#;
+
+        // Test
+        let loaded = ExpiryTable::from_toml(backwards);
+        let inserted = table.insert(
+            "HE",
+            Contract {
+                symbol: "HEK4".to_string(),
+                expiration: date(2024, 5, 14),
+                roll_date: Some(date(2024, 3, 1)),
+            },
+        );
+
+        // Validate
+        assert!(matches!(loaded, Err(Error::Validation(_))));
+        assert!(matches!(inserted, Err(Error::Validation(_))));
+        assert_eq!(table.contracts("HE").len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_schedule_rolls_backwards() -> Result<()> {
+        // Observed roll of HEJ4 comes after HEM4's calendar roll 90 days out
+        let table = ExpiryTable::from_toml(EXPIRIES)?;
+        let symbol: ContinuousSymbol = "HE.n.0".parse()?;
+
+        // Test
+        let result = table.schedule(&symbol, date(2024, 1, 1), date(2024, 5, 1), 90);
+
+        // Validate
+        assert!(matches!(result, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_for_continuous() -> Result<()> {
+        let table = ExpiryTable::from_toml(EXPIRIES)?;
+        let params = RetrieveParams::builder()
+            .symbols(["HE.c.0", "AAPL9"])
+            .start(date(2024, 2, 1))
+            .end(date(2024, 3, 1))
+            .schema(Schema::Ohlcv1D)
+            .build()?;
+
+        // Test
+        let requests = params_for_continuous(&params, &table, 0)?;
+
+        // Validate
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].symbols, vec!["AAPL9".to_string()]);
+        assert_eq!(requests[0].start_ts, params.start_ts);
+        assert_eq!(requests[0].end_ts, params.end_ts);
+        assert_eq!(requests[1].symbols, vec!["HEG4".to_string()]);
+        assert_eq!(requests[2].symbols, vec!["HEJ4".to_string()]);
+        assert_eq!(requests[1].start_ts, params.start_ts);
+        assert_eq!(requests[1].end_ts, requests[2].start_ts);
+        assert_eq!(requests[2].end_ts, params.end_ts);
+        assert_eq!(requests[2].schema, Schema::Ohlcv1D.to_string());
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::validate::ValidationReport;
 use chrono;
 use reqwest;
 use thiserror::Error;
@@ -8,12 +9,43 @@ pub enum Error {
     SqlError(String),
     #[error("JSON error: {0}")]
     JsonError(#[from] serde_json::Error),
+    #[error("CSV error: {0}")]
+    CsvError(#[from] csv::Error),
+    #[cfg(feature = "parquet")]
+    #[error("Arrow error: {0}")]
+    ArrowError(#[from] arrow::error::ArrowError),
+    #[cfg(feature = "parquet")]
+    #[error("Parquet error: {0}")]
+    ParquetError(#[from] parquet::errors::ParquetError),
+    #[error("TOML error: {0}")]
+    TomlError(#[from] toml::de::Error),
     #[error("Parse Error : {0}")]
     ParseError(#[from] chrono::ParseError),
     #[error("Io error: {0}")]
     IOError(#[from] std::io::Error),
     #[error("Request error: {0}")]
     RequestError(#[from] reqwest::Error),
+    #[error("Authentication error ({code}): {message}")]
+    AuthError { code: u16, message: String },
+    #[error("API error ({code} {status}): {message}")]
+    Api {
+        code: u16,
+        status: String,
+        message: String,
+    },
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// Input rejected by the server (400/422) or by checks before a request is sent.
+    #[error("Validation error: {0}")]
+    Validation(String),
+    #[error("Invalid records: {0}")]
+    InvalidRecords(ValidationReport),
+    #[error("Server error ({code}): {message}")]
+    ServerError { code: u16, message: String },
+    #[error("Decode error: {0}")]
+    DecodeError(String),
     #[error("Invalid date format: {0}")]
     InvalidDateFormat(String),
     #[error("Custom error: {0}")]
@@ -0,0 +1,385 @@
+use crate::stream::{RecordBuffer, RecordStream};
+use crate::utils::unix_nanos_to_rfc3339;
+use crate::{error::Error, error::Result};
+use futures_util::StreamExt;
+use mbn::record_enum::RecordEnum;
+use mbn::records::{BboMsg, BidAskPair, Mbp1Msg, OhlcvMsg, RecordHeader, TradeMsg};
+use mbn::symbols::SymbolMap;
+use serde::Serialize;
+use std::io::Write;
+
+/// Fixed-point scale of mbn prices.
+const PRICE_SCALE: f64 = 1e9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+pub(crate) fn price(value: i64) -> f64 {
+    value as f64 / PRICE_SCALE
+}
+
+fn timestamp(value: u64) -> String {
+    unix_nanos_to_rfc3339(value as i64)
+}
+
+pub(crate) fn character(value: i8) -> String {
+    (value as u8 as char).to_string()
+}
+
+#[derive(Debug, Serialize)]
+struct Mbp1Row {
+    ts_event: String,
+    ts_recv: String,
+    symbol: String,
+    instrument_id: u32,
+    action: String,
+    side: String,
+    depth: u8,
+    price: f64,
+    size: u32,
+    flags: u8,
+    sequence: u32,
+    bid_px: f64,
+    ask_px: f64,
+    bid_sz: u32,
+    ask_sz: u32,
+    bid_ct: u32,
+    ask_ct: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct TradeRow {
+    ts_event: String,
+    ts_recv: String,
+    symbol: String,
+    instrument_id: u32,
+    action: String,
+    side: String,
+    depth: u8,
+    price: f64,
+    size: u32,
+    flags: u8,
+    sequence: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct BboRow {
+    ts_event: String,
+    ts_recv: String,
+    symbol: String,
+    instrument_id: u32,
+    side: String,
+    price: f64,
+    size: u32,
+    flags: u8,
+    sequence: u32,
+    bid_px: f64,
+    ask_px: f64,
+    bid_sz: u32,
+    ask_sz: u32,
+    bid_ct: u32,
+    ask_ct: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct OhlcvRow {
+    ts_event: String,
+    symbol: String,
+    instrument_id: u32,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: u64,
+}
+
+/// One exported record, serialized as a flat object.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Row {
+    Mbp1(Mbp1Row),
+    Trade(TradeRow),
+    Bbo(BboRow),
+    Ohlcv(OhlcvRow),
+}
+
+pub(crate) fn symbol(mappings: Option<&SymbolMap>, header: &RecordHeader) -> String {
+    mappings
+        .and_then(|m| m.get_instrument_ticker(header.instrument_id))
+        .unwrap_or_default()
+}
+
+fn mbp1_row(msg: &Mbp1Msg, mappings: Option<&SymbolMap>) -> Row {
+    let BidAskPair {
+        bid_px,
+        ask_px,
+        bid_sz,
+        ask_sz,
+        bid_ct,
+        ask_ct,
+    } = msg.levels[0];
+
+    Row::Mbp1(Mbp1Row {
+        ts_event: timestamp(msg.hd.ts_event),
+        ts_recv: timestamp(msg.ts_recv),
+        symbol: symbol(mappings, &msg.hd),
+        instrument_id: msg.hd.instrument_id,
+        action: character(msg.action),
+        side: character(msg.side),
+        depth: msg.depth,
+        price: price(msg.price),
+        size: msg.size,
+        flags: msg.flags,
+        sequence: msg.sequence,
+        bid_px: price(bid_px),
+        ask_px: price(ask_px),
+        bid_sz,
+        ask_sz,
+        bid_ct,
+        ask_ct,
+    })
+}
+
+fn trade_row(msg: &TradeMsg, mappings: Option<&SymbolMap>) -> Row {
+    Row::Trade(TradeRow {
+        ts_event: timestamp(msg.hd.ts_event),
+        ts_recv: timestamp(msg.ts_recv),
+        symbol: symbol(mappings, &msg.hd),
+        instrument_id: msg.hd.instrument_id,
+        action: character(msg.action),
+        side: character(msg.side),
+        depth: msg.depth,
+        price: price(msg.price),
+        size: msg.size,
+        flags: msg.flags,
+        sequence: msg.sequence,
+    })
+}
+
+fn bbo_row(msg: &BboMsg, mappings: Option<&SymbolMap>) -> Row {
+    let level = msg.levels[0];
+
+    Row::Bbo(BboRow {
+        ts_event: timestamp(msg.hd.ts_event),
+        ts_recv: timestamp(msg.ts_recv),
+        symbol: symbol(mappings, &msg.hd),
+        instrument_id: msg.hd.instrument_id,
+        side: character(msg.side),
+        price: price(msg.price),
+        size: msg.size,
+        flags: msg.flags,
+        sequence: msg.sequence,
+        bid_px: price(level.bid_px),
+        ask_px: price(level.ask_px),
+        bid_sz: level.bid_sz,
+        ask_sz: level.ask_sz,
+        bid_ct: level.bid_ct,
+        ask_ct: level.ask_ct,
+    })
+}
+
+fn ohlcv_row(msg: &OhlcvMsg, mappings: Option<&SymbolMap>) -> Row {
+    Row::Ohlcv(OhlcvRow {
+        ts_event: timestamp(msg.hd.ts_event),
+        symbol: symbol(mappings, &msg.hd),
+        instrument_id: msg.hd.instrument_id,
+        open: price(msg.open),
+        high: price(msg.high),
+        low: price(msg.low),
+        close: price(msg.close),
+        volume: msg.volume,
+    })
+}
+
+/// Converts a record to its exported row, resolving tickers from `mappings`.
+fn row(record: &RecordEnum, mappings: Option<&SymbolMap>) -> Row {
+    match record {
+        RecordEnum::Mbp1(msg) | RecordEnum::Tbbo(msg) => mbp1_row(msg, mappings),
+        RecordEnum::Trade(msg) => trade_row(msg, mappings),
+        RecordEnum::Bbo(msg) => bbo_row(msg, mappings),
+        RecordEnum::Ohlcv(msg) => ohlcv_row(msg, mappings),
+    }
+}
+
+/// Writes decoded records as CSV or JSON Lines, with prices scaled to decimals,
+/// RFC 3339 timestamps and tickers resolved from the metadata mappings.
+pub struct Exporter<W: Write> {
+    output: Output<W>,
+    mappings: Option<SymbolMap>,
+    records: u64,
+}
+
+enum Output<W: Write> {
+    Csv(Box<csv::Writer<W>>),
+    JsonLines(W),
+}
+
+impl<W: Write> Exporter<W> {
+    pub fn new(format: ExportFormat, writer: W) -> Self {
+        let output = match format {
+            ExportFormat::Csv => Output::Csv(Box::new(csv::Writer::from_writer(writer))),
+            ExportFormat::JsonLines => Output::JsonLines(writer),
+        };
+
+        Exporter {
+            output,
+            mappings: None,
+            records: 0,
+        }
+    }
+
+    /// Mappings used to fill in the `symbol` column.
+    pub fn with_mappings(mut self, mappings: SymbolMap) -> Self {
+        self.mappings = Some(mappings);
+        self
+    }
+
+    pub fn records_written(&self) -> u64 {
+        self.records
+    }
+
+    pub fn write(&mut self, record: &RecordEnum) -> Result<()> {
+        let row = row(record, self.mappings.as_ref());
+
+        match &mut self.output {
+            Output::Csv(writer) => writer.serialize(&row)?,
+            Output::JsonLines(writer) => {
+                serde_json::to_writer(&mut *writer, &row)?;
+                writer.write_all(b"\n")?;
+            }
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(self) -> Result<W> {
+        match self.output {
+            Output::Csv(writer) => writer
+                .into_inner()
+                .map_err(|e| Error::IOError(e.into_error())),
+            Output::JsonLines(mut writer) => {
+                writer.flush()?;
+                Ok(writer)
+            }
+        }
+    }
+}
+
+/// Exports an mbn buffer such as the data returned by `get_records`,
+/// returning the number of records written.
+pub fn export_buffer<W: Write>(data: &[u8], format: ExportFormat, writer: W) -> Result<u64> {
+    let mut buffer = RecordBuffer::new();
+    buffer.push(data);
+
+    let mut exporter = Exporter::new(format, writer);
+    while let Some(record) = buffer.next_record()? {
+        if exporter.mappings.is_none() {
+            exporter.mappings = buffer.metadata().map(|m| m.mappings.clone());
+        }
+        exporter.write(&record)?;
+    }
+    buffer.finish()?;
+
+    let records = exporter.records_written();
+    exporter.finish()?;
+    Ok(records)
+}
+
+/// Exports records as they arrive from `stream_records`, returning the
+/// number of records written.
+pub async fn export_stream<W: Write>(
+    mut stream: RecordStream,
+    format: ExportFormat,
+    writer: W,
+) -> Result<u64> {
+    let mut exporter = Exporter::new(format, writer);
+    while let Some(record) = stream.next().await {
+        let record = record?;
+        if exporter.mappings.is_none() {
+            exporter.mappings = stream.metadata().map(|m| m.mappings.clone());
+        }
+        exporter.write(&record)?;
+    }
+
+    let records = exporter.records_written();
+    exporter.finish()?;
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::{Historical, RetrieveParams};
+    use crate::stream::tests::{encoded, mbp};
+    use mbn::enums::Schema;
+
+    #[test]
+    fn test_export_csv() -> Result<()> {
+        let data = encoded(&[mbp(1, 1635728461000000000), mbp(1, 1635728461000000001)]);
+
+        // Test
+        let mut output = Vec::new();
+        let records = export_buffer(&data, ExportFormat::Csv, &mut output)?;
+
+        // Validate
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(records, 2);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("ts_event,ts_recv,symbol,instrument_id,action,side"));
+        assert!(lines[2]
+            .starts_with("2021-11-01T01:01:01.000000001Z,2021-11-01T01:01:01.000000001Z,AAPL9,1,"));
+        assert!(lines[1].contains(",6.77e-6,"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_json_lines() -> Result<()> {
+        let data = encoded(&[mbp(1, 1)]);
+
+        // Test
+        let mut output = Vec::new();
+        export_buffer(&data, ExportFormat::JsonLines, &mut output)?;
+
+        // Validate
+        let line: serde_json::Value = serde_json::from_slice(&output)?;
+        assert_eq!(line["symbol"], "AAPL9");
+        assert_eq!(line["ts_event"], "1970-01-01T00:00:00.000000001Z");
+        assert_eq!(line["price"], 6.77e-6);
+        assert_eq!(line["bid_ct"], 10);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_stream() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(200)
+            .with_body(encoded(&[mbp(1, 1), mbp(1, 2), mbp(1, 3)]))
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let params = RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts: 0,
+            end_ts: 10,
+            schema: Schema::Mbp1.to_string(),
+        };
+
+        // Test
+        let stream = client.stream_records(&params).await?;
+        let mut output = Vec::new();
+        let records = export_stream(stream, ExportFormat::JsonLines, &mut output).await?;
+
+        // Validate
+        assert_eq!(records, 3);
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 3);
+        Ok(())
+    }
+}
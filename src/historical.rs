@@ -1,15 +1,37 @@
-use crate::response::ApiResponse;
-use crate::{error::Error, error::Result, utils::date_to_unix_nanos};
-use futures_util::StreamExt;
+use crate::auth::Auth;
+use crate::batch::{self, BatchOptions, BatchSummary, Batcher, Journal, IDEMPOTENCY_KEY_HEADER};
+use crate::cache::RecordCache;
+use crate::chunk::{self, Checkpoint, ChunkOptions, Stitcher};
+use crate::client::DEFAULT_TIMEOUT;
+#[cfg(feature = "parquet")]
+use crate::columnar::{self, ParquetOptions};
+use crate::progress::{ProgressStream, UploadEvent};
+use crate::response::{ApiDefault, ApiResponse, Created};
+use crate::retry::{self, RetryPolicy};
+use crate::search::InstrumentQuery;
+use crate::stream::{DownloadProgress, RecordBuffer, RecordStream};
+use crate::symbols::InstrumentCache;
+use crate::sync::{SyncPlan, SyncReport};
+use crate::upload::{self, UploadEncoding, UploadTransport, UPLOAD_CHUNK_SIZE};
+use crate::utils::{date_to_unix_nanos, IntoUnixNanos};
+use crate::validate::{self, ValidationReport};
+use crate::{error::Error, error::Result};
+use chrono::Utc;
+use futures_util::{stream, Stream, StreamExt};
+use mbn::enums::Schema;
 use mbn::symbols::Instrument;
-use reqwest::{self, Client, ClientBuilder};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{self, Client, ClientBuilder, RequestBuilder};
 use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::Write;
-use std::time::Duration;
-
-#[derive(Debug, Serialize, Deserialize)]
+use std::collections::HashSet;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::fs;
+use tokio::io::{AsyncRead, AsyncSeekExt, AsyncWriteExt, BufWriter};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RetrieveParams {
     pub symbols: Vec<String>,
     pub start_ts: i64,
@@ -18,6 +40,11 @@ pub struct RetrieveParams {
 }
 
 impl RetrieveParams {
+    /// Builder that validates the request locally before it is sent.
+    pub fn builder() -> RetrieveParamsBuilder {
+        RetrieveParamsBuilder::default()
+    }
+
     pub fn new(symbols: Vec<String>, start: &str, end: &str, schema: &str) -> Result<Self> {
         Ok(RetrieveParams {
             symbols,
@@ -26,27 +53,169 @@ impl RetrieveParams {
             schema: schema.to_string(),
         })
     }
+
+    /// Same request restricted to `[start_ts, end_ts)`.
+    pub fn window(&self, start_ts: i64, end_ts: i64) -> Self {
+        RetrieveParams {
+            symbols: self.symbols.clone(),
+            start_ts,
+            end_ts,
+            schema: self.schema.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RetrieveParamsBuilder {
+    symbols: Vec<String>,
+    start: Option<Result<i64>>,
+    end: Option<Result<i64>>,
+    schema: Option<Schema>,
+}
+
+impl RetrieveParamsBuilder {
+    pub fn symbol(mut self, symbol: &str) -> Self {
+        self.symbols.push(symbol.to_string());
+        self
+    }
+
+    pub fn symbols<I, S>(mut self, symbols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.symbols.extend(symbols.into_iter().map(Into::into));
+        self
+    }
+
+    /// Inclusive start, as nanoseconds or any `chrono` date or datetime.
+    pub fn start<T: IntoUnixNanos>(mut self, start: T) -> Self {
+        self.start = Some(start.into_unix_nanos());
+        self
+    }
+
+    /// Exclusive end, as nanoseconds or any `chrono` date or datetime.
+    pub fn end<T: IntoUnixNanos>(mut self, end: T) -> Self {
+        self.end = Some(end.into_unix_nanos());
+        self
+    }
+
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn build(self) -> Result<RetrieveParams> {
+        if self.symbols.is_empty() {
+            return Err(Error::Validation(
+                "At least one symbol is required.".to_string(),
+            ));
+        }
+        if self.symbols.iter().any(|s| s.trim().is_empty()) {
+            return Err(Error::Validation("Symbols cannot be empty.".to_string()));
+        }
+
+        let start_ts = self
+            .start
+            .ok_or_else(|| Error::Validation("Missing start.".to_string()))??;
+        let end_ts = self
+            .end
+            .ok_or_else(|| Error::Validation("Missing end.".to_string()))??;
+        if start_ts >= end_ts {
+            return Err(Error::Validation(format!(
+                "Start ({}) must be before end ({}).",
+                start_ts, end_ts
+            )));
+        }
+
+        let schema = self
+            .schema
+            .ok_or_else(|| Error::Validation("Missing schema.".to_string()))?;
+
+        Ok(RetrieveParams {
+            symbols: self.symbols,
+            start_ts,
+            end_ts,
+            schema: schema.to_string(),
+        })
+    }
 }
 
 #[derive(Clone)]
 pub struct Historical {
     base_url: String,
     client: Client,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+    upload: UploadTransport,
 }
 
 impl Historical {
     pub fn new(base_url: &str) -> Self {
         let client = ClientBuilder::new()
-            .timeout(Duration::from_secs(20000)) // Set timeout to 120 seconds
+            .timeout(DEFAULT_TIMEOUT)
             .build()
             .expect("Failed to build HTTP client");
 
+        Historical::with_client(base_url, client)
+    }
+
+    /// Reuses an existing HTTP client, e.g. the pool owned by a `MidasClient`.
+    pub fn with_client(base_url: &str, client: Client) -> Self {
         Historical {
             base_url: base_url.to_string(),
             client,
+            auth: None,
+            retry: RetryPolicy::default(),
+            upload: UploadTransport::default(),
         }
     }
 
+    /// Attaches credentials applied to every request.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Wire format of uploaded records, JSON by default. Binary forms fall
+    /// back to JSON if the server answers `415 Unsupported Media Type`.
+    pub fn with_upload_encoding(mut self, encoding: UploadEncoding) -> Self {
+        self.upload = UploadTransport::new(encoding);
+        self
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        retry::send(request, self.auth.as_ref(), &self.retry).await
+    }
+
+    /// `send` for GETs that read the whole response, re-sending the request if
+    /// the body fails part way through.
+    async fn send_and_read<T, F, Fut>(&self, request: RequestBuilder, read: F) -> Result<T>
+    where
+        F: FnMut(Response) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        retry::send_and_read(request, self.auth.as_ref(), &self.retry, read).await
+    }
+
+    async fn post_records(&self, url: &str, data: &[u8], headers: HeaderMap) -> Result<Response> {
+        self.upload
+            .post(
+                &self.client,
+                url,
+                data,
+                headers,
+                self.auth.as_ref(),
+                &self.retry,
+            )
+            .await
+    }
+
     fn url(&self, endpoint: &str) -> String {
         format!(
             "{}{}{}",
@@ -57,42 +226,67 @@ impl Historical {
     }
 
     // Instruments
-    pub async fn create_symbol(&self, instrument: &Instrument) -> Result<ApiResponse<u32>> {
+    pub async fn create_symbol(
+        &self,
+        instrument: &Instrument,
+    ) -> Result<ApiResponse<Option<Created>>> {
         let url = self.url("instruments/create");
 
         // Send the POST request
-        let response: Response = self.client.post(&url).json(instrument).send().await?;
+        let response: Response = self.send(self.client.post(&url).json(instrument)).await?;
 
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<u32>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<u32>::from_response(response).await?;
-        Ok(api_response)
+        // Deserialize the API response and return it, even if it indicates failure
+        let api_response = ApiResponse::<Option<i32>>::from_response(response).await?;
+        Ok(api_response.into_created())
     }
 
     pub async fn get_symbol(&self, ticker: &String) -> Result<ApiResponse<u32>> {
         let url = self.url("instruments/get");
 
         // Send GET request
-        let response = self.client.get(&url).json(ticker).send().await?;
+        self.send_and_read(
+            self.client.get(&url).json(ticker),
+            ApiResponse::<u32>::from_response,
+        )
+        .await
+    }
 
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<u32>::from_response(response).await;
+    /// Full instrument for `ticker`, data = None if it doesn't exist.
+    ///
+    /// Servers without `instruments/get_instrument` are answered from the full
+    /// instrument list instead.
+    pub async fn get_instrument(&self, ticker: &str) -> Result<ApiResponse<Option<Instrument>>> {
+        let url = self.url("instruments/get_instrument");
+        let response = self
+            .send(self.client.get(&url).query(&[("ticker", ticker)]))
+            .await?;
+
+        // Deserialize the API response and return it, even if it indicates failure
+        if let Some(api_response) = route_response(response).await? {
+            return Ok(api_response);
         }
 
-        let api_response = ApiResponse::<u32>::from_response(response).await?;
-        Ok(api_response)
+        let instrument = self
+            .list_symbols()
+            .await?
+            .into_result()?
+            .into_iter()
+            .find(|instrument| instrument.ticker == ticker);
+        Ok(ApiResponse::new("success", "", StatusCode::OK, instrument))
+    }
+
+    /// Snapshot of the instrument universe for resolving ids and tickers
+    /// locally, see `InstrumentCache::refresh`.
+    pub async fn instrument_cache(&self) -> Result<InstrumentCache> {
+        let mut cache = InstrumentCache::new();
+        cache.refresh(self).await?;
+        Ok(cache)
     }
 
     /// Returns data = ""
     pub async fn delete_symbol(&self, id: &i32) -> Result<ApiResponse<String>> {
         let url = self.url("instruments/delete");
-        let response = self.client.delete(&url).json(id).send().await?;
+        let response = self.send(self.client.delete(&url).json(id)).await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
@@ -106,16 +300,11 @@ impl Historical {
 
     pub async fn list_symbols(&self) -> Result<ApiResponse<Vec<Instrument>>> {
         let url = self.url("instruments/list");
-        let response = self.client.get(&url).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<Instrument>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<Instrument>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url),
+            ApiResponse::<Vec<Instrument>>::from_response,
+        )
+        .await
     }
 
     pub async fn list_vendor_symbols(
@@ -123,16 +312,11 @@ impl Historical {
         vendor: &String,
     ) -> Result<ApiResponse<Vec<Instrument>>> {
         let url = self.url("instruments/vendor_list");
-        let response = self.client.get(&url).json(vendor).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<Instrument>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<Instrument>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url).json(vendor),
+            ApiResponse::<Vec<Instrument>>::from_response,
+        )
+        .await
     }
 
     pub async fn update_symbol(
@@ -141,7 +325,9 @@ impl Historical {
         id: &i32,
     ) -> Result<ApiResponse<String>> {
         let url = self.url("instruments/update");
-        let response = self.client.put(&url).json(&(instrument, id)).send().await?;
+        let response = self
+            .send(self.client.put(&url).json(&(instrument, id)))
+            .await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
@@ -153,178 +339,731 @@ impl Historical {
         Ok(api_response)
     }
 
+    /// Instruments matching `query`. Filters are sent to the server's
+    /// `instruments/search` endpoint; servers without it return the full list
+    /// (or the vendor's list), which is filtered locally.
+    ///
+    /// On those servers every call downloads that list, so for repeated
+    /// searches keep an `InstrumentCache` and use `InstrumentCache::search`.
+    pub async fn search_instruments(
+        &self,
+        query: &InstrumentQuery,
+    ) -> Result<impl Iterator<Item = Instrument>> {
+        let url = self.url("instruments/search");
+        let response = self.send(self.client.get(&url).query(query)).await?;
+
+        let instruments = match route_response::<Vec<Instrument>>(response).await? {
+            Some(api_response) => api_response.into_result()?,
+            None => match query.vendor_filter() {
+                Some(vendor) => self.list_vendor_symbols(&vendor.to_string()).await?,
+                None => self.list_symbols().await?,
+            }
+            .into_result()?,
+        };
+
+        let query = query.clone();
+        Ok(instruments
+            .into_iter()
+            .filter(move |instrument| query.matches(instrument)))
+    }
+
+    /// Diffs `desired` against the server's instruments, limited to `vendor`
+    /// when given so other vendors' instruments aren't deactivated.
+    pub async fn plan_instrument_sync(
+        &self,
+        desired: &[Instrument],
+        vendor: Option<&String>,
+    ) -> Result<SyncPlan> {
+        let current = match vendor {
+            Some(vendor) => self.list_vendor_symbols(vendor).await?,
+            None => self.list_symbols().await?,
+        };
+        SyncPlan::new(desired, &current.into_result()?)
+    }
+
+    /// Applies every change in `plan`, continuing past failures, which are
+    /// listed in the report whether the server rejected the change or the
+    /// request itself failed.
+    pub async fn apply_instrument_sync(&self, plan: &SyncPlan) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+
+        for instrument in &plan.create {
+            let response = self.create_symbol(instrument).await;
+            record_change(
+                &mut report.created,
+                &mut report.failed,
+                instrument,
+                response,
+            );
+        }
+        for (id, instrument) in &plan.update {
+            let response = self.update_symbol(instrument, id).await;
+            record_change(
+                &mut report.updated,
+                &mut report.failed,
+                instrument,
+                response,
+            );
+        }
+        for (id, instrument) in &plan.deactivate {
+            let response = self.update_symbol(instrument, id).await;
+            record_change(
+                &mut report.deactivated,
+                &mut report.failed,
+                instrument,
+                response,
+            );
+        }
+
+        Ok(report)
+    }
+
     // Market data
     pub async fn create_mbp(&self, data: &[u8]) -> Result<ApiResponse<String>> {
+        self.create_mbp_with_progress(data, |_| {}).await
+    }
+
+    /// Uploads records, calling `progress` for each message the server
+    /// streams back.
+    pub async fn create_mbp_with_progress<F>(
+        &self,
+        data: &[u8],
+        mut progress: F,
+    ) -> Result<ApiResponse<String>>
+    where
+        F: FnMut(&UploadEvent),
+    {
         let url = self.url("mbp/create");
-        let response = self.client.post(&url).json(data).send().await?;
+        let response = self.post_records(&url, data, HeaderMap::new()).await?;
+        upload_progress(response, &mut progress).await
+    }
 
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<String>::from_response(response).await;
+    /// Uploads records, returning the messages the server streams back as a
+    /// `Stream` instead of waiting for the upload to finish.
+    pub async fn create_mbp_stream(&self, data: &[u8]) -> Result<ProgressStream> {
+        let url = self.url("mbp/create");
+        let response = self.post_records(&url, data, HeaderMap::new()).await?;
+        ProgressStream::from_response(response).await
+    }
+
+    /// Checks a buffer for `create_mbp` locally, against the instruments
+    /// returned by `list_symbols`.
+    pub async fn validate_mbp(&self, data: &[u8]) -> Result<ValidationReport> {
+        let instruments = self.list_symbols().await?.into_result()?;
+        let ids: HashSet<u32> = instruments
+            .iter()
+            .filter_map(|instrument| instrument.instrument_id)
+            .collect();
+
+        Ok(validate::validate_records(data, &ids))
+    }
+
+    /// `create_mbp` that only uploads the buffer if `validate_mbp` finds no
+    /// issues, returning `Error::InvalidRecords` with the report otherwise.
+    pub async fn create_mbp_checked(&self, data: &[u8]) -> Result<ApiResponse<String>> {
+        let report = self.validate_mbp(data).await?;
+        if !report.is_valid() {
+            return Err(Error::InvalidRecords(report));
         }
+        self.create_mbp(data).await
+    }
 
-        // let api_response = ApiResponse::<String>::from_response(response).await?;
-        // Ok(api_response)
-        // Stream the server's response
-        let mut stream = response.bytes_stream();
-        // let mut last_response;
-
-        // Output the streamed response directly to the user
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(bytes) => {
-                    let bytes_str = String::from_utf8_lossy(&bytes);
-                    match serde_json::from_str::<ApiResponse<String>>(&bytes_str) {
-                        Ok(response) => {
-                            println!("{:?}", response.message);
-                            if response.status != "success" {
-                                return Ok(response);
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error while receiving chunk: {:?}", e);
-                            return Err(Error::from(e));
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error while reading chunk: {:?}", e);
-                    return Err(Error::from(e));
-                }
+    /// Uploads a records-only mbn stream, such as a file opened with
+    /// `tokio::fs::File`, in batches of at most `options.max_batch_bytes`, each
+    /// sent with an idempotency key derived from its position and content.
+    /// Only the batch being sent is held in memory.
+    ///
+    /// Batches are retried under the client's retry policy even when it
+    /// doesn't retry POSTs, since the key makes a repeated batch safe.
+    ///
+    /// Acknowledged keys are recorded in the journal at `journal_path`, so
+    /// rerunning after a failure skips the batches the server already accepted.
+    /// The journal is kept afterwards, making a rerun over the same data a no-op.
+    pub async fn create_mbp_batched<R, P>(
+        &self,
+        records: R,
+        options: &BatchOptions,
+        journal_path: P,
+    ) -> Result<BatchSummary>
+    where
+        R: AsyncRead + Unpin,
+        P: AsRef<Path>,
+    {
+        let journal_path = journal_path.as_ref();
+        let url = self.url("mbp/create");
+        let retry = self.retry.clone().retry_post(true);
+        let mut journal = Journal::load(journal_path).await?;
+        let mut batcher = Batcher::new(records, options);
+        let mut summary = BatchSummary::default();
+
+        while let Some(batch) = batcher.next_batch().await? {
+            let key = batch::batch_key(&batch);
+            summary.batches += 1;
+            summary.records += batch.records;
+
+            if journal.acknowledged.contains(&key) {
+                summary.skipped += 1;
+                continue;
             }
-        }
 
-        let api_response = ApiResponse::new("success", "", StatusCode::OK, "".to_string());
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                IDEMPOTENCY_KEY_HEADER,
+                HeaderValue::from_str(&key).expect("Hex keys are valid header values"),
+            );
+            let response = self
+                .upload
+                .post(
+                    &self.client,
+                    &url,
+                    &batch.bytes,
+                    headers,
+                    self.auth.as_ref(),
+                    &retry,
+                )
+                .await?;
+            let response = upload_progress(response, &mut |_| {}).await?;
+
+            // Conflict means the server already holds a batch with this key
+            if !response.is_success() && response.code != StatusCode::CONFLICT.as_u16() {
+                return Err(response.into_error());
+            }
 
-        Ok(api_response)
+            journal.acknowledged.insert(key);
+            journal.save(journal_path).await?;
+            summary.uploaded += 1;
+        }
+
+        Ok(summary)
     }
 
     pub async fn create_mbp_from_file(&self, file_path: &str) -> Result<ApiResponse<String>> {
+        self.create_mbp_from_file_with_progress(file_path, |_| {})
+            .await
+    }
+
+    /// Loads a file on the server's filesystem, calling `progress` for each
+    /// message the server streams back.
+    pub async fn create_mbp_from_file_with_progress<F>(
+        &self,
+        file_path: &str,
+        mut progress: F,
+    ) -> Result<ApiResponse<String>>
+    where
+        F: FnMut(&UploadEvent),
+    {
         let url = self.url("mbp/bulk_upload");
         let response = self
+            .send(self.client.post(&url).json(&file_path)) // Ensure you send the file path correctly
+            .await?;
+        upload_progress(response, &mut progress).await
+    }
+
+    /// Loads a file on the server's filesystem, returning the messages the
+    /// server streams back as a `Stream`.
+    pub async fn create_mbp_from_file_stream(&self, file_path: &str) -> Result<ProgressStream> {
+        let url = self.url("mbp/bulk_upload");
+        let response = self.send(self.client.post(&url).json(&file_path)).await?;
+        ProgressStream::from_response(response).await
+    }
+
+    /// Streams a local mbn file to the server as `application/octet-stream`,
+    /// reading `UPLOAD_CHUNK_SIZE` bytes at a time, so the file doesn't need to
+    /// exist on the server's filesystem.
+    pub async fn create_mbp_from_local_file(&self, file_path: &str) -> Result<ApiResponse<String>> {
+        self.create_mbp_from_local_file_with_progress(file_path, |_| {})
+            .await
+    }
+
+    pub async fn create_mbp_from_local_file_with_progress<F>(
+        &self,
+        file_path: &str,
+        mut progress: F,
+    ) -> Result<ApiResponse<String>>
+    where
+        F: FnMut(&UploadEvent),
+    {
+        let url = self.url("mbp/upload");
+        let (body, length) = upload::file_body(file_path, UPLOAD_CHUNK_SIZE).await?;
+        let request = self
             .client
             .post(&url)
-            .json(&file_path) // Ensure you send the file path correctly
-            .send()
-            .await?;
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .header(CONTENT_LENGTH, length)
+            .body(body);
+        let response = self.send(request).await?;
+        upload_progress(response, &mut progress).await
+    }
+
+    pub async fn get_records(&self, params: &RetrieveParams) -> Result<ApiResponse<Vec<u8>>> {
+        let url = self.url("mbp/get");
+        self.send_and_read(self.client.get(&url).json(params), read_records)
+            .await
+    }
+
+    /// Streams records as they are received instead of buffering the whole response.
+    pub async fn stream_records(&self, params: &RetrieveParams) -> Result<RecordStream> {
+        let url = self.url("mbp/get");
+        let response = self.send(self.client.get(&url).json(params)).await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<String>::from_response(response).await;
+            let api_response = ApiResponse::<String>::from_response(response).await?;
+            return Err(api_response.into_error());
         }
 
-        // Stream the server's response
-        let mut stream = response.bytes_stream();
-
-        // Output the streamed response directly to the user
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(bytes) => {
-                    let bytes_str = String::from_utf8_lossy(&bytes);
-                    match serde_json::from_str::<ApiResponse<String>>(&bytes_str) {
-                        Ok(response) => {
-                            println!("{:?}", response.message);
-
-                            if response.status != "success" {
-                                return Ok(response);
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error while receiving chunk: {:?}", e);
-                            return Err(Error::from(e));
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error while reading chunk: {:?}", e);
-                    return Err(Error::from(e));
-                }
-            }
-        }
-
-        let api_response = ApiResponse::new("success", "", StatusCode::OK, "".to_string());
+        Ok(RecordStream::new(response.bytes_stream().boxed()))
+    }
 
-        Ok(api_response)
+    pub async fn get_records_to_file(
+        &self,
+        params: &RetrieveParams,
+        file_path: &str,
+    ) -> Result<()> {
+        self.get_records_to_file_with_progress(params, file_path, |_| {})
+            .await?;
+        Ok(())
     }
 
-    pub async fn get_records(&self, params: &RetrieveParams) -> Result<ApiResponse<Vec<u8>>> {
+    /// Streams records straight to disk, calling `progress` after every received chunk.
+    ///
+    /// Data is written to `<file_path>.part` and renamed once complete, so
+    /// `file_path` only ever holds a full download.
+    pub async fn get_records_to_file_with_progress<F>(
+        &self,
+        params: &RetrieveParams,
+        file_path: &str,
+        mut progress: F,
+    ) -> Result<DownloadProgress>
+    where
+        F: FnMut(&DownloadProgress),
+    {
         let url = self.url("mbp/get");
-        let response = self.client.get(&url).json(params).send().await?;
+        let response = self.send(self.client.get(&url).json(params)).await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<u8>>::from_response(response).await;
+            let api_response = ApiResponse::<String>::from_response(response).await?;
+            return Err(api_response.into_error());
         }
 
-        // Ensure the response is streamed properly
-        let mut data = Vec::new();
-        let mut stream = response.bytes_stream(); // Correct usage of bytes_stream here
+        let temp_path = format!("{}.part", file_path);
+        let result = write_body(response, &temp_path, &mut progress).await;
 
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(bytes) => data.extend_from_slice(&bytes),
-                Err(e) => {
-                    println!("Error while receiving chunk: {:?}", e);
-                    return Err(Error::from(e));
-                }
+        match result {
+            Ok(summary) => {
+                fs::rename(&temp_path, file_path).await?;
+                Ok(summary)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                Err(e)
             }
         }
+    }
 
-        // Deserialize the data into the ApiResponse
-        let api_response = ApiResponse::new("success", "", StatusCode::OK, data);
-        Ok(api_response)
+    /// Streams records into a Parquet file, one row group of
+    /// `options.row_group_size` rows at a time, returning the number of
+    /// records written.
+    #[cfg(feature = "parquet")]
+    pub async fn get_records_to_parquet(
+        &self,
+        params: &RetrieveParams,
+        file_path: &str,
+        options: &ParquetOptions,
+    ) -> Result<u64> {
+        let stream = self.stream_records(params).await?;
+
+        let temp_path = format!("{}.part", file_path);
+        let result = columnar::write_parquet(stream, &params.schema, &temp_path, options).await;
+
+        match result {
+            Ok(records) => {
+                fs::rename(&temp_path, file_path).await?;
+                Ok(records)
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                Err(e)
+            }
+        }
     }
 
-    pub async fn get_records_to_file(
+    /// Splits the request into windows per `options`, fetching up to
+    /// `options.concurrency` of them at once. The results are combined in
+    /// timestamp order under a single metadata header, matching the buffer a
+    /// single `get_records` call would return.
+    pub async fn get_records_chunked(
+        &self,
+        params: &RetrieveParams,
+        options: &ChunkOptions,
+    ) -> Result<ApiResponse<Vec<u8>>> {
+        let mut stitcher = Stitcher::new(params);
+        let mut records = Vec::new();
+        let mut windows = self.fetch_windows(params, options)?;
+
+        while let Some(response) = windows.next().await {
+            let (_, response) = response?;
+
+            // Windows without data are skipped
+            if response.code == StatusCode::NOT_FOUND.as_u16() {
+                continue;
+            }
+            if !response.is_success() {
+                return Ok(response);
+            }
+            records.extend_from_slice(stitcher.add(&response.data)?);
+        }
+
+        let mut data = stitcher.header()?;
+        data.extend_from_slice(&records);
+        Ok(ApiResponse::new("success", "", StatusCode::OK, data))
+    }
+
+    /// Chunked retrieval written to `file_path`, holding at most
+    /// `options.concurrency` windows in memory.
+    pub async fn get_records_chunked_to_file(
         &self,
         params: &RetrieveParams,
+        options: &ChunkOptions,
         file_path: &str,
     ) -> Result<()> {
-        let response = self.get_records(params).await?;
+        let records_path = format!("{}.records.part", file_path);
+        let temp_path = format!("{}.part", file_path);
+
+        let result = async {
+            let mut stitcher = Stitcher::new(params);
+            let mut file = BufWriter::new(fs::File::create(&records_path).await?);
+            let mut windows = self.fetch_windows(params, options)?;
+
+            while let Some(response) = windows.next().await {
+                let (_, response) = response?;
+                if let Some(data) = window_data(response)? {
+                    file.write_all(stitcher.add(&data)?).await?;
+                }
+            }
+            file.flush().await?;
+            drop(file);
+
+            write_output(&stitcher.header()?, &records_path, &temp_path).await
+        }
+        .await;
 
-        // Create or open the file
-        let mut file = File::create(file_path)?;
+        let _ = fs::remove_file(&records_path).await;
+        match result {
+            Ok(()) => {
+                fs::rename(&temp_path, file_path).await?;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = fs::remove_file(&temp_path).await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Chunked retrieval to `file_path` that can pick up where a failed run
+    /// stopped.
+    ///
+    /// After each window is written, a checkpoint with the window end and the
+    /// bytes written so far is saved to `<file_path>.checkpoint`. Calling this
+    /// again with the same params discards anything past the checkpoint and
+    /// continues from the next window. The checkpoint and partial files are
+    /// removed once the download completes.
+    pub async fn get_records_to_file_resumable(
+        &self,
+        params: &RetrieveParams,
+        options: &ChunkOptions,
+        file_path: &str,
+    ) -> Result<()> {
+        let records_path = format!("{}.records.part", file_path);
+        let temp_path = format!("{}.part", file_path);
+        let checkpoint_path = format!("{}.checkpoint", file_path);
+
+        let mut stitcher = Stitcher::new(params);
+        let mut checkpoint = match Checkpoint::load(&checkpoint_path).await? {
+            Some(checkpoint) if checkpoint.params == *params => checkpoint,
+            _ => Checkpoint::new(params),
+        };
+        if let Some(metadata) = &checkpoint.metadata {
+            stitcher.add(metadata)?;
+        }
 
-        // Write the binary data to the file
-        let _ = file.write_all(&response.data);
-        // .ok_or_else(|| {
-        //     std::io::Error::new(std::io::ErrorKind::Other, "Error with returned buffer")
-        // })?)?;
+        // Drop anything written after the last checkpoint
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&records_path)
+            .await?;
+        if file.metadata().await?.len() < checkpoint.offset {
+            return Err(Error::CustomError(format!(
+                "{} is shorter than its checkpoint, remove {} to restart.",
+                records_path, checkpoint_path
+            )));
+        }
+        file.set_len(checkpoint.offset).await?;
+        let mut file = BufWriter::new(file);
+        file.seek(SeekFrom::Start(checkpoint.offset)).await?;
+
+        let remaining = params.window(checkpoint.completed_until, params.end_ts);
+        let mut windows = self.fetch_windows(&remaining, options)?;
+
+        while let Some(response) = windows.next().await {
+            let (end, response) = response?;
+            if let Some(data) = window_data(response)? {
+                let records = stitcher.add(&data)?;
+                file.write_all(records).await?;
+                file.flush().await?;
+                file.get_ref().sync_data().await?;
+                checkpoint.offset += records.len() as u64;
+            }
+            checkpoint.completed_until = end;
+            checkpoint.metadata = stitcher.metadata_bytes();
+            checkpoint.save(&checkpoint_path).await?;
+        }
+        drop(file);
 
+        write_output(&stitcher.header()?, &records_path, &temp_path).await?;
+        fs::rename(&temp_path, file_path).await?;
+        fs::remove_file(&records_path).await?;
+        fs::remove_file(&checkpoint_path).await?;
         Ok(())
     }
+
+    /// `get_records` backed by a local cache, requesting only the parts of the
+    /// range not already cached.
+    ///
+    /// Data after the current time may still be arriving, so that part of the
+    /// range is requested on every call and never cached.
+    pub async fn get_records_cached(
+        &self,
+        params: &RetrieveParams,
+        cache: &mut RecordCache,
+    ) -> Result<ApiResponse<Vec<u8>>> {
+        let now = Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX);
+        let mut stitcher = Stitcher::new(params);
+        let mut records = Vec::new();
+
+        let settled = params.window(params.start_ts, params.end_ts.min(now));
+        if settled.start_ts < settled.end_ts {
+            for (start, end) in cache.gaps(&settled) {
+                let window = settled.window(start, end);
+                let response = self.get_records(&window).await?;
+
+                // Empty windows are cached too, so they aren't requested again
+                if response.code == StatusCode::NOT_FOUND.as_u16() {
+                    cache.insert(&window, &[]).await?;
+                    continue;
+                }
+                if !response.is_success() {
+                    return Ok(response);
+                }
+                cache.insert(&window, &response.data).await?;
+            }
+
+            let data = cache.read(&settled).await?;
+            records.extend_from_slice(stitcher.add(&data)?);
+            cache.evict().await?;
+        }
+
+        if params.end_ts > now {
+            let open = params.window(params.start_ts.max(now), params.end_ts);
+            let response = self.get_records(&open).await?;
+            if response.is_success() {
+                records.extend_from_slice(stitcher.add(&response.data)?);
+            } else if response.code != StatusCode::NOT_FOUND.as_u16() {
+                return Ok(response);
+            }
+        }
+
+        let mut data = stitcher.header()?;
+        data.extend_from_slice(&records);
+        Ok(ApiResponse::new("success", "", StatusCode::OK, data))
+    }
+
+    /// Window responses in order, each with the end of its window, with up to
+    /// `options.concurrency` in flight.
+    fn fetch_windows<'a>(
+        &'a self,
+        params: &'a RetrieveParams,
+        options: &ChunkOptions,
+    ) -> Result<impl Stream<Item = Result<(i64, ApiResponse<Vec<u8>>)>> + 'a> {
+        let windows = chunk::windows(params, options.chunk_by)?;
+
+        Ok(stream::iter(windows)
+            .map(move |(start, end)| async move {
+                let response = self.get_records(&params.window(start, end)).await?;
+                Ok((end, response))
+            })
+            .buffered(options.concurrency.max(1)))
+    }
+}
+
+/// Reports the progress messages the server streams back during an upload,
+/// returning the first failure or success once the stream ends.
+async fn upload_progress<F>(response: Response, progress: &mut F) -> Result<ApiResponse<String>>
+where
+    F: FnMut(&UploadEvent),
+{
+    // Check for HTTP status
+    if response.status() != StatusCode::OK {
+        // Deserialize the API response and return it, even if it indicates failure
+        let api_response = ApiResponse::<String>::from_response(response).await?;
+        progress(&UploadEvent::Failed {
+            message: api_response.message.clone(),
+        });
+        return Ok(api_response);
+    }
+
+    let mut messages = ProgressStream::new(response);
+    while let Some(message) = messages.next().await {
+        let response = match message {
+            Ok(response) => response,
+            Err(e) => {
+                progress(&UploadEvent::Failed {
+                    message: e.to_string(),
+                });
+                return Err(e);
+            }
+        };
+
+        if response.status != "success" {
+            progress(&UploadEvent::Failed {
+                message: response.message.clone(),
+            });
+            return Ok(response);
+        }
+        progress(&UploadEvent::progress(&response.message));
+    }
+
+    progress(&UploadEvent::Completed);
+    let api_response = ApiResponse::new("success", "", StatusCode::OK, "".to_string());
+
+    Ok(api_response)
+}
+
+/// Reads a `get_records` response, returning the API response as is when the
+/// server rejected the request.
+async fn read_records(response: Response) -> Result<ApiResponse<Vec<u8>>> {
+    // Check for HTTP status
+    if response.status() != StatusCode::OK {
+        // Deserialize the API response and return it, even if it indicates failure
+        return ApiResponse::<Vec<u8>>::from_response(response).await;
+    }
+
+    // Ensure the response is streamed properly
+    let mut data = Vec::new();
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        data.extend_from_slice(&chunk?);
+    }
+
+    Ok(ApiResponse::new("success", "", StatusCode::OK, data))
+}
+
+/// Counts a change the server accepted in `applied`, otherwise lists the
+/// instrument in `failed` with the server's message or the request's error.
+fn record_change<T>(
+    applied: &mut usize,
+    failed: &mut Vec<(String, String)>,
+    instrument: &Instrument,
+    response: Result<ApiResponse<T>>,
+) {
+    match response {
+        Ok(response) if response.is_success() => *applied += 1,
+        Ok(response) => failed.push((instrument.ticker.clone(), response.message)),
+        Err(e) => failed.push((instrument.ticker.clone(), e.to_string())),
+    }
+}
+
+/// Response from a route older servers may not have, or `None` if the server
+/// doesn't know it. Older servers answer unknown routes with a 404 or 405 and
+/// no `ApiResponse` body; other failures without one are mapped from the status.
+async fn route_response<T>(response: Response) -> Result<Option<ApiResponse<T>>>
+where
+    T: serde::de::DeserializeOwned + ApiDefault,
+{
+    let status = response.status();
+    let body = response.text().await?;
+
+    match serde_json::from_str::<ApiResponse<T>>(&body) {
+        Ok(api_response) => Ok(Some(api_response)),
+        Err(_) if status == StatusCode::NOT_FOUND || status == StatusCode::METHOD_NOT_ALLOWED => {
+            Ok(None)
+        }
+        Err(_) if !status.is_success() => {
+            Err(ApiResponse::<T>::with_default("failed", &body, status.as_u16()).into_error())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Data of a window response, `None` for windows without data.
+fn window_data(response: ApiResponse<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+    if response.code == StatusCode::NOT_FOUND.as_u16() {
+        return Ok(None);
+    }
+    if !response.is_success() {
+        return Err(response.into_error());
+    }
+    Ok(Some(response.data))
+}
+
+/// Writes `header` followed by the contents of `records_path` to `path`.
+async fn write_output(header: &[u8], records_path: &str, path: &str) -> Result<()> {
+    let mut output = BufWriter::new(fs::File::create(path).await?);
+    output.write_all(header).await?;
+    let mut records = fs::File::open(records_path).await?;
+    tokio::io::copy(&mut records, &mut output).await?;
+    output.flush().await?;
+    output.into_inner().sync_all().await?;
+    Ok(())
+}
+
+async fn write_body<F>(response: Response, path: &str, progress: &mut F) -> Result<DownloadProgress>
+where
+    F: FnMut(&DownloadProgress),
+{
+    let mut file = BufWriter::new(fs::File::create(path).await?);
+    let mut stream = response.bytes_stream();
+    let mut buffer = RecordBuffer::new();
+    let mut summary = DownloadProgress::default();
+
+    while let Some(chunk) = stream.next().await {
+        let bytes = chunk?;
+        file.write_all(&bytes).await?;
+        buffer.push(&bytes);
+
+        // Count complete records, keeping only partial ones in memory
+        while buffer.next_record_bytes()?.is_some() {
+            summary.records_decoded += 1;
+        }
+        summary.bytes_received += bytes.len() as u64;
+        progress(&summary);
+    }
+
+    buffer.finish()?;
+    file.flush().await?;
+    file.into_inner().sync_all().await?;
+    Ok(summary)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::stream::tests::{encoded, mbp};
     use dotenv::dotenv;
     use mbn::decode::Decoder;
     use mbn::encode::RecordEncoder;
-    use mbn::enums::{Action, Schema};
+    use mbn::enums::Action;
     use mbn::record_ref::RecordRef;
     use mbn::records::{BidAskPair, Mbp1Msg, RecordHeader};
     use mbn::symbols::Instrument;
     use mbn::symbols::Vendors;
-    use regex::Regex;
     use serial_test::serial;
     use std::io::Cursor;
 
-    fn get_id_from_string(message: &str) -> Option<i32> {
-        let re = Regex::new(r"\d+$").unwrap();
-
-        if let Some(captures) = re.captures(message) {
-            if let Some(matched) = captures.get(0) {
-                let number: i32 = matched.as_str().parse().unwrap();
-                return Some(number);
-            }
-        }
-        None
-    }
-
     async fn create_dummy_instrument(client: &Historical) -> Result<i32> {
         // Create instrument
         let instrument = Instrument::new(
@@ -340,9 +1079,7 @@ mod tests {
         );
 
         let create_response = client.create_symbol(&instrument).await?;
-        let id =
-            get_id_from_string(&create_response.message).expect("Error getting id from message.");
-        Ok(id)
+        Ok(create_response.data.expect("Expected an id").id)
     }
 
     #[allow(dead_code)]
@@ -432,7 +1169,7 @@ mod tests {
 
         // Test
         let response = client.create_symbol(&instrument).await?;
-        let id = get_id_from_string(&response.message).expect("Error getting id from message.");
+        let id = response.data.expect("Expected an id").id;
 
         // Validate
         assert_eq!(response.code, 200);
@@ -811,6 +1548,45 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_get_records_to_file_progress() -> Result<()> {
+        let records = vec![mbp(1, 1), mbp(1, 2), mbp(1, 3)];
+        let body = encoded(&records);
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(200)
+            .with_body(body.clone())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let params = RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts: 0,
+            end_ts: 10,
+            schema: Schema::Mbp1.to_string(),
+        };
+        let path = std::env::temp_dir().join("midas_client_progress_test.bin");
+        let path = path.to_str().unwrap();
+
+        // Test
+        let mut updates = Vec::new();
+        let summary = client
+            .get_records_to_file_with_progress(&params, path, |p| updates.push(*p))
+            .await?;
+
+        // Validate
+        assert_eq!(summary.records_decoded, 3);
+        assert_eq!(summary.bytes_received, body.len() as u64);
+        assert_eq!(updates.last(), Some(&summary));
+        assert_eq!(tokio::fs::read(path).await?, body);
+        assert!(!std::path::Path::new(&format!("{}.part", path)).exists());
+
+        // Cleanup
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     #[serial]
     // #[ignore]
@@ -967,4 +1743,42 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_params_builder() -> Result<()> {
+        let params = RetrieveParams::builder()
+            .symbols(["HE.n.0", "ZC.n.0"])
+            .start(chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap())
+            .end(1704240000000000000)
+            .schema(Schema::Bbo1M)
+            .build()?;
+
+        // Validate
+        assert_eq!(params.start_ts, 1704153600000000000);
+        assert_eq!(params.end_ts, 1704240000000000000);
+        assert_eq!(params.schema, Schema::Bbo1M.to_string());
+        assert_eq!(params.symbols, vec!["HE.n.0", "ZC.n.0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_params_builder_validation() {
+        let base = || {
+            RetrieveParams::builder()
+                .symbol("AAPL9")
+                .schema(Schema::Mbp1)
+        };
+
+        // Validate
+        let reversed = base().start(20).end(10).build();
+        assert!(matches!(reversed, Err(Error::Validation(_))));
+        let missing_end = base().start(10).build();
+        assert!(matches!(missing_end, Err(Error::Validation(_))));
+        let no_symbols = RetrieveParams::builder()
+            .start(10)
+            .end(20)
+            .schema(Schema::Mbp1)
+            .build();
+        assert!(matches!(no_symbols, Err(Error::Validation(_))));
+    }
 }
@@ -1,8 +1,40 @@
-// pub mod client;
+pub mod auth;
+pub mod batch;
+pub mod cache;
+pub mod calendar;
+pub mod chunk;
+pub mod client;
+#[cfg(feature = "parquet")]
+pub mod columnar;
+pub mod continuous;
 pub mod error;
+pub mod export;
 pub mod historical;
+pub mod progress;
 pub mod response;
+pub mod retry;
+pub mod search;
+pub mod stream;
+pub mod symbols;
+pub mod sync;
 pub mod trading;
+pub mod upload;
 pub mod utils;
+pub mod validate;
 
+pub use self::auth::{Auth, TokenProvider};
+pub use self::batch::{BatchOptions, BatchSummary};
+pub use self::chunk::{ChunkBy, ChunkOptions};
+pub use self::client::{MidasClient, MidasClientBuilder};
+#[cfg(feature = "parquet")]
+pub use self::columnar::ParquetOptions;
+pub use self::continuous::{ContinuousSymbol, ExpiryTable, RollRule};
 pub use self::error::{Error, Result};
+pub use self::historical::{RetrieveParams, RetrieveParamsBuilder};
+pub use self::progress::{ProgressStream, UploadEvent};
+pub use self::retry::RetryPolicy;
+pub use self::search::InstrumentQuery;
+pub use self::symbols::InstrumentCache;
+pub use self::sync::{SyncPlan, SyncReport};
+pub use self::upload::UploadEncoding;
+pub use self::validate::ValidationReport;
@@ -0,0 +1,335 @@
+use crate::response::ApiResponse;
+use crate::stream::{push_compacted, ChunkDecoder, DecodedBody};
+use crate::{error::Error, error::Result};
+use futures_util::Stream;
+use reqwest::{Response, StatusCode};
+use serde_json::Deserializer;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Reported to upload progress callbacks as the server streams back messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UploadEvent {
+    /// Intermediate message as the server sent it. `records` is only set for
+    /// the server's `Inserted <n> records` message, other text is never
+    /// parsed for counts.
+    Progress {
+        message: String,
+        records: Option<u64>,
+    },
+    Completed,
+    /// Failure reported by the server, or an error reading its response.
+    Failed {
+        message: String,
+    },
+}
+
+impl UploadEvent {
+    pub(crate) fn progress(message: &str) -> Self {
+        UploadEvent::Progress {
+            message: message.to_string(),
+            records: inserted_records(message),
+        }
+    }
+}
+
+/// Record count of an `Inserted <n> records` (or `Inserted 1 record`) message.
+fn inserted_records(message: &str) -> Option<u64> {
+    let rest = message.trim().strip_prefix("Inserted ")?;
+    let count = rest
+        .strip_suffix(" records")
+        .or_else(|| rest.strip_suffix(" record"))?;
+
+    if !count.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    count.parse().ok()
+}
+
+/// Incremental parser for the progress messages streamed back by upload
+/// endpoints. Messages are JSON objects separated by newlines or written back
+/// to back; a message split across chunks is held until the rest arrives.
+#[derive(Debug, Default)]
+pub struct ProgressBuffer {
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl ProgressBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        push_compacted(&mut self.buffer, &mut self.position, bytes);
+    }
+
+    /// Returns the next complete message, or `None` until more data arrives.
+    pub fn next_message(&mut self) -> Result<Option<ApiResponse<String>>> {
+        let available = &self.buffer[self.position..];
+        let mut messages = Deserializer::from_slice(available).into_iter::<ApiResponse<String>>();
+
+        match messages.next() {
+            Some(Ok(message)) => {
+                self.position += messages.byte_offset();
+                Ok(Some(message))
+            }
+            Some(Err(e)) if e.is_eof() => Ok(None),
+            Some(Err(e)) => Err(Error::from(e)),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks that the stream didn't end part way through a message.
+    pub fn finish(&self) -> Result<()> {
+        let remaining = &self.buffer[self.position..];
+        if remaining.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+
+        Err(Error::DecodeError(format!(
+            "Stream ended with incomplete message: {}",
+            String::from_utf8_lossy(remaining)
+        )))
+    }
+}
+
+impl ChunkDecoder for ProgressBuffer {
+    type Item = ApiResponse<String>;
+
+    fn push(&mut self, bytes: &[u8]) {
+        ProgressBuffer::push(self, bytes)
+    }
+
+    fn next_item(&mut self) -> Result<Option<ApiResponse<String>>> {
+        self.next_message()
+    }
+
+    fn finish(&self) -> Result<()> {
+        ProgressBuffer::finish(self)
+    }
+}
+
+/// Stream of the progress messages in an upload response, in the order the
+/// server sent them.
+pub struct ProgressStream {
+    body: DecodedBody<ProgressBuffer>,
+}
+
+impl ProgressStream {
+    pub(crate) fn new(response: Response) -> Self {
+        ProgressStream {
+            body: DecodedBody::new(Box::pin(response.bytes_stream()), ProgressBuffer::new()),
+        }
+    }
+
+    /// Stream of the messages in `response`, or the server's error if it
+    /// rejected the upload before streaming any.
+    pub(crate) async fn from_response(response: Response) -> Result<Self> {
+        if response.status() != StatusCode::OK {
+            let api_response = ApiResponse::<String>::from_response(response).await?;
+            return Err(api_response.into_error());
+        }
+        Ok(ProgressStream::new(response))
+    }
+}
+
+impl Stream for ProgressStream {
+    type Item = Result<ApiResponse<String>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.body.poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    fn message(status: &str, message: &str) -> String {
+        json!({"status": status, "message": message, "code": 200, "data": ""}).to_string()
+    }
+
+    #[test]
+    fn test_inserted_records() {
+        // Validate
+        assert_eq!(inserted_records("Inserted 10 records"), Some(10));
+        assert_eq!(inserted_records("Inserted 1 record"), Some(1));
+        assert_eq!(inserted_records("Batch 3: Inserted 10 records"), None);
+        assert_eq!(inserted_records("Inserted 50% of records"), None);
+        assert_eq!(inserted_records("Inserted +5 records"), None);
+        assert_eq!(inserted_records("Upload complete"), None);
+    }
+
+    #[test]
+    fn test_progress_buffer_split() -> Result<()> {
+        let line = format!("{}\n", message("success", "Inserted 10 records"));
+        let (first, second) = line.as_bytes().split_at(line.len() / 2);
+        let mut buffer = ProgressBuffer::new();
+
+        // Test
+        buffer.push(first);
+        let partial = buffer.next_message()?;
+        buffer.push(second);
+        let complete = buffer.next_message()?;
+
+        // Validate
+        assert!(partial.is_none());
+        assert_eq!(complete.unwrap().message, "Inserted 10 records");
+        assert!(buffer.next_message()?.is_none());
+        buffer.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_buffer_coalesced() -> Result<()> {
+        let chunk = format!(
+            "{}\n{}{}",
+            message("success", "one"),
+            message("success", "two"),
+            message("failed", "three")
+        );
+        let mut buffer = ProgressBuffer::new();
+
+        // Test
+        buffer.push(chunk.as_bytes());
+        let mut messages = Vec::new();
+        while let Some(message) = buffer.next_message()? {
+            messages.push(message.message);
+        }
+
+        // Validate
+        assert_eq!(messages, vec!["one", "two", "three"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_buffer_incomplete() {
+        let mut buffer = ProgressBuffer::new();
+        buffer.push(b"{\"status\": \"succ");
+
+        // Test
+        let result = buffer.finish();
+
+        // Validate
+        assert!(matches!(result, Err(Error::DecodeError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_with_progress() -> Result<()> {
+        let body = format!(
+            "{}\n{}\n",
+            message("success", "Inserted 10 records"),
+            message("success", "Upload complete")
+        );
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(200)
+            .with_body(body)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let mut events = Vec::new();
+        let response = client
+            .create_mbp_with_progress(&[], |event| events.push(event.clone()))
+            .await?;
+
+        // Validate
+        assert_eq!(response.status, "success");
+        assert_eq!(
+            events,
+            vec![
+                UploadEvent::Progress {
+                    message: "Inserted 10 records".to_string(),
+                    records: Some(10),
+                },
+                UploadEvent::Progress {
+                    message: "Upload complete".to_string(),
+                    records: None,
+                },
+                UploadEvent::Completed,
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_from_file_stream() -> Result<()> {
+        let body = format!(
+            "{}{}\n",
+            message("success", "Inserted 10 records"),
+            message("success", "Upload complete")
+        );
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/historical/mbp/bulk_upload")
+            .with_status(200)
+            .with_body(body)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let stream = client.create_mbp_from_file_stream("data.bin").await?;
+        let messages: Vec<String> = stream
+            .map(|message| message.map(|m| m.message))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<_>>()?;
+
+        // Validate
+        assert_eq!(messages, vec!["Inserted 10 records", "Upload complete"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_stream_rejected() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(500)
+            .with_body(json!({"status": "failed", "message": "Crashed", "code": 500}).to_string())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let result = client.create_mbp_stream(&[]).await;
+
+        // Validate
+        assert!(matches!(result, Err(Error::ServerError { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_coalesced_progress() -> Result<()> {
+        let body = format!(
+            "{}\n{}\n",
+            message("success", "Inserted 10 records"),
+            message("failed", "Duplicate record")
+        );
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(200)
+            .with_body(body)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let response = client.create_mbp(&[]).await?;
+
+        // Validate
+        assert_eq!(response.status, "failed");
+        assert_eq!(response.message, "Duplicate record");
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::Error;
 use reqwest::{Response, StatusCode};
 use serde::{Deserialize, Serialize};
 
@@ -34,6 +35,30 @@ impl<T> ApiDefault for Option<T> {
     }
 }
 
+/// Identifier of a newly created resource.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Created {
+    pub id: i32,
+}
+
+impl Created {
+    /// Parses the id older servers append to the end of the response message.
+    pub fn from_message(message: &str) -> Option<Self> {
+        let message = message.trim_end();
+        let start = message
+            .rfind(|c: char| !c.is_ascii_digit())
+            .map_or(0, |i| i + 1);
+
+        message[start..].parse().ok().map(|id| Created { id })
+    }
+}
+
+impl ApiDefault for Created {
+    fn default_value() -> Self {
+        Created::default()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RawApiResponse {
     pub status: String,
@@ -90,6 +115,62 @@ impl<T: serde::de::DeserializeOwned + ApiDefault> ApiResponse<T> {
     }
 }
 
+impl ApiResponse<Option<i32>> {
+    /// Takes the id from `data`, falling back to the message for servers that don't return it.
+    /// Failed creates keep `data = None`, so they never carry an id.
+    pub(crate) fn into_created(self) -> ApiResponse<Option<Created>> {
+        let created = if self.is_success() {
+            self.data
+                .filter(|id| *id > 0)
+                .map(|id| Created { id })
+                .or_else(|| Created::from_message(&self.message))
+        } else {
+            None
+        };
+
+        ApiResponse {
+            status: self.status,
+            message: self.message,
+            code: self.code,
+            data: created,
+        }
+    }
+}
+
+impl<T> ApiResponse<T> {
+    pub fn is_success(&self) -> bool {
+        self.status == "success" && self.code < 400
+    }
+
+    /// Returns the data on success, otherwise a typed error built from the status, code and message.
+    pub fn into_result(self) -> crate::Result<T> {
+        if self.is_success() {
+            return Ok(self.data);
+        }
+        Err(self.into_error())
+    }
+
+    /// Classifies the response as an error regardless of its status.
+    pub(crate) fn into_error(self) -> Error {
+        match self.code {
+            404 => Error::NotFound(self.message),
+            409 => Error::Conflict(self.message),
+            400 | 422 => Error::Validation(self.message),
+            // Duplicates are currently reported as internal server errors
+            _ if self.message.to_lowercase().contains("duplicate") => Error::Conflict(self.message),
+            code if code >= 500 => Error::ServerError {
+                code,
+                message: self.message,
+            },
+            code => Error::Api {
+                code,
+                status: self.status,
+                message: self.message,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -122,4 +203,72 @@ mod tests {
         let response = ApiResponse::new(status, msg, code, "12345".to_string());
         assert_eq!(response, api_response);
     }
+
+    #[test]
+    fn test_created_from_message() {
+        // Validate
+        assert_eq!(
+            Created::from_message("Successfully created instrument with id 42"),
+            Some(Created { id: 42 })
+        );
+        assert_eq!(Created::from_message("Created"), None);
+        assert_eq!(Created::from_message(""), None);
+    }
+
+    #[test]
+    fn test_into_created() {
+        let with_data = ApiResponse::new("success", "Created 7", StatusCode::OK, Some(12));
+        let without_data = ApiResponse::new("success", "Created 7", StatusCode::OK, None);
+        let failed = ApiResponse::new("failed", "Error", StatusCode::CONFLICT, None);
+        let failed_with_message =
+            ApiResponse::new("failed", "Error creating 7", StatusCode::CONFLICT, None);
+
+        // Validate
+        assert_eq!(with_data.into_created().data, Some(Created { id: 12 }));
+        assert_eq!(without_data.into_created().data, Some(Created { id: 7 }));
+        assert_eq!(failed.into_created().data, None);
+        assert_eq!(failed_with_message.into_created().data, None);
+    }
+
+    #[test]
+    fn test_into_result_success() -> crate::Result<()> {
+        let response = ApiResponse::new("success", "", StatusCode::OK, 12);
+
+        // Test
+        let data = response.into_result()?;
+
+        // Validate
+        assert_eq!(data, 12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_result_errors() {
+        let not_found = ApiResponse::new("success", "Missing", StatusCode::NOT_FOUND, 0);
+        let duplicate = ApiResponse::new(
+            "failed",
+            "duplicate key value violates unique constraint",
+            StatusCode::INTERNAL_SERVER_ERROR,
+            0,
+        );
+        let validation = ApiResponse::new("failed", "Bad", StatusCode::BAD_REQUEST, 0);
+        let server = ApiResponse::new("failed", "Oops", StatusCode::INTERNAL_SERVER_ERROR, 0);
+        let other = ApiResponse::new("failed", "Odd", StatusCode::OK, 0);
+
+        // Validate
+        assert!(matches!(not_found.into_result(), Err(Error::NotFound(_))));
+        assert!(matches!(duplicate.into_result(), Err(Error::Conflict(_))));
+        assert!(matches!(
+            validation.into_result(),
+            Err(Error::Validation(_))
+        ));
+        assert!(matches!(
+            server.into_result(),
+            Err(Error::ServerError { code: 500, .. })
+        ));
+        assert!(matches!(
+            other.into_result(),
+            Err(Error::Api { code: 200, .. })
+        ));
+    }
 }
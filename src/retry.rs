@@ -0,0 +1,503 @@
+use crate::auth::{self, Auth};
+use crate::error::{Error, Result};
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Why a request is being retried.
+#[derive(Debug, Clone)]
+pub enum RetryReason {
+    Status(StatusCode),
+    Error(String),
+}
+
+/// Passed to the `on_retry` hook before each retry.
+#[derive(Debug, Clone)]
+pub struct RetryEvent {
+    pub method: Method,
+    pub url: String,
+    /// Attempt that just failed, starting at 1.
+    pub attempt: u32,
+    pub delay: Duration,
+    pub reason: RetryReason,
+}
+
+type RetryHook = Arc<dyn Fn(&RetryEvent) + Send + Sync>;
+
+/// Controls how failed requests are retried.
+///
+/// Idempotent methods (GET, HEAD, PUT, DELETE) are retried automatically,
+/// POST requests only when `retry_post` is enabled. Requests with streaming
+/// bodies are never retried since they cannot be replayed.
+///
+/// Connection failures and retryable statuses are retried for every allowed
+/// method. GETs that read their whole response are also sent again when the
+/// body fails part way through; streamed responses such as `stream_records`
+/// are not, since their records have already been handed out.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: bool,
+    retryable_statuses: Vec<StatusCode>,
+    respect_retry_after: bool,
+    max_retry_after: Duration,
+    retry_post: bool,
+    on_retry: Option<RetryHook>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+            retryable_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            respect_retry_after: true,
+            max_retry_after: Duration::from_secs(300),
+            retry_post: false,
+            on_retry: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Policy that sends every request exactly once.
+    pub fn none() -> Self {
+        RetryPolicy::default().max_attempts(1)
+    }
+
+    /// Total attempts including the first request.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Randomizes each delay between zero and the computed backoff.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn retryable_statuses(mut self, statuses: Vec<StatusCode>) -> Self {
+        self.retryable_statuses = statuses;
+        self
+    }
+
+    /// Waits for the server's `Retry-After` header when present.
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// Longest `Retry-After` honored, independent of `max_backoff`.
+    pub fn max_retry_after(mut self, max: Duration) -> Self {
+        self.max_retry_after = max;
+        self
+    }
+
+    /// Opts POST requests (e.g. `create_mbp`) into retries.
+    pub fn retry_post(mut self, retry: bool) -> Self {
+        self.retry_post = retry;
+        self
+    }
+
+    /// Called before sleeping for each retry.
+    pub fn on_retry<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RetryEvent) + Send + Sync + 'static,
+    {
+        self.on_retry = Some(Arc::new(hook));
+        self
+    }
+
+    fn allows(&self, method: &Method) -> bool {
+        match *method {
+            Method::GET | Method::HEAD | Method::PUT | Method::DELETE => true,
+            Method::POST => self.retry_post,
+            _ => false,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let delay = self.initial_backoff.mul_f64(factor).min(self.max_backoff);
+
+        if self.jitter && !delay.is_zero() {
+            delay.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+        } else {
+            delay
+        }
+    }
+
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        match retry_after {
+            Some(delay) if self.respect_retry_after => delay.min(self.max_retry_after),
+            _ => self.backoff(attempt),
+        }
+    }
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .field("retryable_statuses", &self.retryable_statuses)
+            .field("respect_retry_after", &self.respect_retry_after)
+            .field("max_retry_after", &self.max_retry_after)
+            .field("retry_post", &self.retry_post)
+            .finish()
+    }
+}
+
+/// Parses `Retry-After` as either delay seconds or an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+fn is_transient(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || error.is_request()
+}
+
+/// Failure reading a response body that a fresh request may not hit.
+fn is_transient_body(error: &reqwest::Error) -> bool {
+    error.is_body() || error.is_decode() || error.is_timeout()
+}
+
+/// Applies credentials and sends the request, retrying per `policy`.
+pub(crate) async fn send(
+    request: RequestBuilder,
+    auth: Option<&Auth>,
+    policy: &RetryPolicy,
+) -> Result<Response> {
+    let request = match auth {
+        Some(auth) => auth.apply(request).await?,
+        None => request,
+    };
+    let (client, request) = request.build_split();
+    let mut request = request?;
+    let allowed = policy.allows(request.method());
+    let mut attempt = 1;
+
+    loop {
+        // Streaming bodies can't be cloned, those requests are sent once
+        let next = if allowed && attempt < policy.max_attempts {
+            request.try_clone()
+        } else {
+            None
+        };
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let result = client.execute(request).await;
+
+        let next = match next {
+            Some(next) => next,
+            None => return auth::check_auth(result?).await,
+        };
+
+        let (delay, reason) = match &result {
+            Ok(response) if policy.retryable_statuses.contains(&response.status()) => (
+                policy.delay(attempt, retry_after(response)),
+                RetryReason::Status(response.status()),
+            ),
+            Err(e) if is_transient(e) => (
+                policy.delay(attempt, None),
+                RetryReason::Error(e.to_string()),
+            ),
+            _ => return auth::check_auth(result?).await,
+        };
+
+        if let Some(hook) = &policy.on_retry {
+            hook(&RetryEvent {
+                method,
+                url,
+                attempt,
+                delay,
+                reason,
+            });
+        }
+
+        tokio::time::sleep(delay).await;
+        request = next;
+        attempt += 1;
+    }
+}
+
+/// `send` for a GET followed by `read` on the response, sending the request
+/// again when the body fails part way through with a transient error.
+pub(crate) async fn send_and_read<T, F, Fut>(
+    mut request: RequestBuilder,
+    auth: Option<&Auth>,
+    policy: &RetryPolicy,
+    mut read: F,
+) -> Result<T>
+where
+    F: FnMut(Response) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+
+    loop {
+        let next = if attempt < policy.max_attempts {
+            request.try_clone()
+        } else {
+            None
+        };
+        let response = send(request, auth, policy).await?;
+        let url = response.url().to_string();
+
+        let error = match read(response).await {
+            Err(Error::RequestError(e)) if is_transient_body(&e) => e,
+            result => return result,
+        };
+        let next = match next {
+            Some(next) => next,
+            None => return Err(error.into()),
+        };
+
+        let delay = policy.delay(attempt, None);
+        if let Some(hook) = &policy.on_retry {
+            hook(&RetryEvent {
+                method: Method::GET,
+                url,
+                attempt,
+                delay,
+                reason: RetryReason::Error(error.to_string()),
+            });
+        }
+
+        tokio::time::sleep(delay).await;
+        request = next;
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn list_body() -> String {
+        json!({"status": "success", "message": "", "code": 200, "data": []}).to_string()
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy::default()
+            .initial_backoff(Duration::ZERO)
+            .jitter(false)
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::default()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300))
+            .jitter(false);
+
+        // Validate
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_backoff_jitter_bounded() {
+        let policy = RetryPolicy::default().initial_backoff(Duration::from_millis(100));
+
+        // Validate
+        for _ in 0..100 {
+            assert!(policy.backoff(1) <= Duration::from_millis(100));
+        }
+    }
+
+    #[test]
+    fn test_retry_after_exceeds_max_backoff() {
+        let policy = RetryPolicy::default()
+            .max_backoff(Duration::from_secs(10))
+            .max_retry_after(Duration::from_secs(120));
+
+        // Validate
+        let requested = Some(Duration::from_secs(60));
+        assert_eq!(policy.delay(1, requested), Duration::from_secs(60));
+        let requested = Some(Duration::from_secs(600));
+        assert_eq!(policy.delay(1, requested), Duration::from_secs(120));
+        let ignored = policy.respect_retry_after(false).jitter(false);
+        assert_eq!(ignored.delay(1, requested), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_allowed_methods() {
+        let policy = RetryPolicy::default();
+
+        // Validate
+        assert!(policy.allows(&Method::GET));
+        assert!(policy.allows(&Method::DELETE));
+        assert!(!policy.allows(&Method::POST));
+        assert!(policy.retry_post(true).allows(&Method::POST));
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_status() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let unavailable = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(503)
+            .expect(2)
+            .create_async()
+            .await;
+        let success = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(200)
+            .with_body(list_body())
+            .create_async()
+            .await;
+
+        let retries = Arc::new(AtomicU32::new(0));
+        let counter = retries.clone();
+        let policy = fast_policy().on_retry(move |event| {
+            assert!(matches!(event.reason, RetryReason::Status(_)));
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let client = Historical::new(&server.url()).with_retry(policy);
+
+        // Test
+        let response = client.list_symbols().await?;
+
+        // Validate
+        assert_eq!(response.status, "success");
+        assert_eq!(retries.load(Ordering::SeqCst), 2);
+        unavailable.assert_async().await;
+        success.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(502)
+            .with_body(
+                json!({"status": "failed", "message": "Bad gateway", "code": 502}).to_string(),
+            )
+            .expect(2)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_retry(fast_policy().max_attempts(2));
+
+        // Test
+        let response = client.list_symbols().await?;
+
+        // Validate
+        assert_eq!(response.code, 502);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_not_retried_by_default() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/historical/mbp/create")
+            .with_status(503)
+            .with_body(
+                json!({"status": "failed", "message": "Unavailable", "code": 503}).to_string(),
+            )
+            .expect(1)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_retry(fast_policy());
+
+        // Test
+        let response = client.create_mbp(&[]).await?;
+
+        // Validate
+        assert_eq!(response.status, "failed");
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_truncated_body() -> Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let body = list_body();
+        let server = tokio::spawn(async move {
+            // First response promises more bytes than it sends
+            for truncate in [true, false] {
+                let (mut socket, _) = listener.accept().await?;
+                let mut request = [0; 4096];
+                let _ = socket.read(&mut request).await?;
+                let sent = if truncate { &body[..10] } else { &body[..] };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    sent
+                );
+                socket.write_all(response.as_bytes()).await?;
+            }
+            Ok::<_, std::io::Error>(())
+        });
+
+        let retries = Arc::new(AtomicU32::new(0));
+        let counter = retries.clone();
+        let policy = fast_policy().on_retry(move |event| {
+            assert!(matches!(event.reason, RetryReason::Error(_)));
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let client = Historical::new(&url).with_retry(policy);
+
+        // Test
+        let response = client.list_symbols().await?;
+
+        // Validate
+        assert_eq!(response.status, "success");
+        assert_eq!(retries.load(Ordering::SeqCst), 1);
+        server.await.expect("Server task panicked")?;
+        Ok(())
+    }
+}
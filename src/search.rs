@@ -0,0 +1,320 @@
+use mbn::symbols::{Instrument, Vendors};
+use serde::{Serialize, Serializer};
+
+/// Matches `text` against a glob where `*` is any run of characters and `?`
+/// any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Last `*` seen and the text position it currently covers up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Sends the vendor by its display name, as `list_vendor_symbols` does.
+fn serialize_vendor<S: Serializer>(
+    vendor: &Option<Vendors>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match vendor {
+        Some(vendor) => serializer.collect_str(vendor),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Filters for `Historical::search_instruments`. Unset filters match every
+/// instrument, set ones must all match.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct InstrumentQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ticker: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "serialize_vendor"
+    )]
+    vendor: Option<Vendors>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dataset: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stype: Option<String>,
+}
+
+impl InstrumentQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ticker glob, e.g. `HE.*` or `Z?.n.0`.
+    pub fn ticker(mut self, pattern: &str) -> Self {
+        self.ticker = Some(pattern.to_string());
+        self
+    }
+
+    pub fn ticker_prefix(self, prefix: &str) -> Self {
+        self.ticker(&format!("{}*", prefix))
+    }
+
+    /// Case-insensitive substring of the instrument name.
+    pub fn name_contains(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn vendor(mut self, vendor: Vendors) -> Self {
+        self.vendor = Some(vendor);
+        self
+    }
+
+    pub fn dataset(mut self, dataset: &str) -> Self {
+        self.dataset = Some(dataset.to_string());
+        self
+    }
+
+    pub fn active(mut self, active: bool) -> Self {
+        self.active = Some(active);
+        self
+    }
+
+    /// Instrument type, e.g. `continuous`.
+    pub fn instrument_type(mut self, stype: &str) -> Self {
+        self.stype = Some(stype.to_string());
+        self
+    }
+
+    pub(crate) fn vendor_filter(&self) -> Option<&Vendors> {
+        self.vendor.as_ref()
+    }
+
+    pub fn matches(&self, instrument: &Instrument) -> bool {
+        let equals = |filter: &Option<String>, value: Option<&str>| match filter {
+            Some(filter) => value.is_some_and(|v| v.eq_ignore_ascii_case(filter)),
+            None => true,
+        };
+
+        self.ticker
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, &instrument.ticker))
+            && self.name.as_ref().is_none_or(|name| {
+                instrument
+                    .name
+                    .to_lowercase()
+                    .contains(&name.to_lowercase())
+            })
+            && self
+                .vendor
+                .as_ref()
+                .is_none_or(|vendor| instrument.vendor == *vendor)
+            && equals(&self.dataset, instrument.dataset.as_deref())
+            && equals(&self.stype, instrument.stype.as_deref())
+            && self.active.is_none_or(|active| instrument.active == active)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Result;
+    use crate::historical::Historical;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn instrument(ticker: &str, name: &str, active: bool) -> Instrument {
+        Instrument::new(
+            None,
+            ticker,
+            name,
+            Vendors::Databento,
+            Some("continuous".to_string()),
+            Some("GLBX.MDP3".to_string()),
+            1,
+            1,
+            active,
+        )
+    }
+
+    fn tickers(instruments: impl Iterator<Item = Instrument>) -> Vec<String> {
+        instruments.map(|i| i.ticker).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("HE.*", "HE.n.0"));
+        assert!(glob_match("Z?.n.0", "ZC.n.0"));
+        assert!(glob_match("*.n.*", "ZC.n.0"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("HE.*", "ZC.n.0"));
+        assert!(!glob_match("Z?.n.0", "ZC.c.0"));
+    }
+
+    #[test]
+    fn test_query_matches() {
+        let hogs = instrument("HE.n.0", "Lean Hogs", true);
+
+        assert!(InstrumentQuery::new().matches(&hogs));
+        assert!(InstrumentQuery::new()
+            .ticker_prefix("HE")
+            .name_contains("hogs")
+            .vendor(Vendors::Databento)
+            .dataset("glbx.mdp3")
+            .instrument_type("continuous")
+            .active(true)
+            .matches(&hogs));
+        assert!(!InstrumentQuery::new().active(false).matches(&hogs));
+        assert!(!InstrumentQuery::new().dataset("XNAS.ITCH").matches(&hogs));
+    }
+
+    #[tokio::test]
+    async fn test_search_instruments_fallback() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _search = server
+            .mock("GET", "/historical/instruments/search")
+            .match_query(Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+        let _list = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": [
+                        instrument("HE.n.0", "Lean Hogs", true),
+                        instrument("HE.n.1", "Lean Hogs", false),
+                        instrument("ZC.n.0", "Corn", true),
+                    ],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let query = InstrumentQuery::new().ticker_prefix("HE").active(true);
+        let results = client.search_instruments(&query).await?;
+
+        // Validate
+        assert_eq!(tickers(results), vec!["HE.n.0"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_instruments_vendor_fallback() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _search = server
+            .mock("GET", "/historical/instruments/search")
+            .match_query(Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+        let list = server
+            .mock("GET", "/historical/instruments/vendor_list")
+            .match_body(Matcher::Json(json!(Vendors::Databento.to_string())))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": [instrument("ZC.n.0", "Corn", true)],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let query = InstrumentQuery::new().vendor(Vendors::Databento);
+        let results = client.search_instruments(&query).await?;
+
+        // Validate
+        list.assert_async().await;
+        assert_eq!(tickers(results), vec!["ZC.n.0"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_instruments_pushdown() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let search = server
+            .mock("GET", "/historical/instruments/search")
+            .match_query(Matcher::AllOf(vec![
+                Matcher::UrlEncoded("ticker".into(), "ZC*".into()),
+                Matcher::UrlEncoded("vendor".into(), Vendors::Databento.to_string()),
+                Matcher::UrlEncoded("active".into(), "true".into()),
+            ]))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": [instrument("ZC.n.0", "Corn", true)],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let query = InstrumentQuery::new()
+            .ticker_prefix("ZC")
+            .vendor(Vendors::Databento)
+            .active(true);
+        let results = client.search_instruments(&query).await?;
+
+        // Validate
+        search.assert_async().await;
+        assert_eq!(tickers(results), vec!["ZC.n.0"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_instruments_server_error() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _search = server
+            .mock("GET", "/historical/instruments/search")
+            .match_query(Matcher::Any)
+            .with_status(502)
+            .with_body("<html>Bad Gateway</html>")
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let result = client.search_instruments(&InstrumentQuery::new()).await;
+
+        // Validate
+        assert!(matches!(
+            result,
+            Err(crate::Error::ServerError { code: 502, .. })
+        ));
+        Ok(())
+    }
+}
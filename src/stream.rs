@@ -0,0 +1,548 @@
+use crate::{error::Error, error::Result};
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::Stream;
+use mbn::metadata::Metadata;
+use mbn::record_enum::RecordEnum;
+use mbn::record_ref::RecordRef;
+use mbn::records::RecordHeader;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// Size of the little-endian length prefix written before the encoded metadata.
+const METADATA_PREFIX_LEN: usize = 2;
+
+/// Alignment every record shares with its leading `RecordHeader`.
+const RECORD_ALIGN: usize = mem::align_of::<RecordHeader>();
+
+/// Parses the length-prefixed metadata at the start of `bytes`, returning it with
+/// the number of bytes consumed, or `None` if the buffer doesn't hold all of it yet.
+pub(crate) fn read_metadata(bytes: &[u8]) -> Result<Option<(Metadata, usize)>> {
+    if bytes.len() < METADATA_PREFIX_LEN {
+        return Ok(None);
+    }
+
+    let length = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+    let end = METADATA_PREFIX_LEN + length;
+    if bytes.len() < end {
+        return Ok(None);
+    }
+
+    let metadata = Metadata::deserialize(&bytes[METADATA_PREFIX_LEN..end])
+        .map_err(|e| Error::DecodeError(format!("Invalid metadata: {:?}", e)))?;
+    Ok(Some((metadata, end)))
+}
+
+/// Encodes metadata with the same length prefix the server writes.
+pub(crate) fn write_metadata(metadata: &Metadata) -> Vec<u8> {
+    let encoded = metadata.serialize();
+    let mut bytes = (encoded.len() as u16).to_le_bytes().to_vec();
+    bytes.extend_from_slice(&encoded);
+    bytes
+}
+
+/// Reads the header at the start of `bytes`, which may not be aligned.
+pub(crate) fn read_header(bytes: &[u8]) -> Option<RecordHeader> {
+    if bytes.len() < mem::size_of::<RecordHeader>() {
+        return None;
+    }
+
+    // SAFETY: the length is checked above and `read_unaligned` has no alignment requirement
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const RecordHeader) })
+}
+
+fn is_aligned(bytes: &[u8]) -> bool {
+    bytes.as_ptr().align_offset(RECORD_ALIGN) == 0
+}
+
+/// Decodes a single complete record in place. `bytes` must hold exactly one
+/// record, aligned as returned by `RecordBuffer::next_record_bytes`.
+pub(crate) fn decode_record(bytes: &[u8]) -> Result<RecordEnum> {
+    let size = read_header(bytes).map(|header| header.record_size());
+    if size != Some(bytes.len()) {
+        return Err(Error::DecodeError(format!(
+            "Invalid record length {}",
+            bytes.len()
+        )));
+    }
+    if !is_aligned(bytes) {
+        return Err(Error::DecodeError("Record is not aligned".to_string()));
+    }
+
+    // SAFETY: `bytes` is exactly the record its header describes and starts on a
+    // `RecordHeader` boundary, both checked above, as `RecordRef::new` requires
+    let record = unsafe { RecordRef::new(bytes) };
+    RecordEnum::from_ref(record).map_err(|e| Error::DecodeError(format!("{:?}", e)))
+}
+
+/// Header of a record of any type.
+pub(crate) fn header(record: &RecordEnum) -> &RecordHeader {
+    match record {
+        RecordEnum::Mbp1(msg) | RecordEnum::Tbbo(msg) => &msg.hd,
+        RecordEnum::Trade(msg) => &msg.hd,
+        RecordEnum::Bbo(msg) => &msg.hd,
+        RecordEnum::Ohlcv(msg) => &msg.hd,
+    }
+}
+
+/// Timestamp the server filters `start_ts` and `end_ts` on: `ts_recv`, or the
+/// bar start for OHLCV records, which don't carry one.
+pub(crate) fn ts_recv(record: &RecordEnum) -> u64 {
+    match record {
+        RecordEnum::Mbp1(msg) | RecordEnum::Tbbo(msg) => msg.ts_recv,
+        RecordEnum::Trade(msg) => msg.ts_recv,
+        RecordEnum::Bbo(msg) => msg.ts_recv,
+        RecordEnum::Ohlcv(msg) => msg.hd.ts_event,
+    }
+}
+
+/// Appends `bytes` to `buffer`, first dropping the bytes before `position`
+/// that have already been consumed.
+pub(crate) fn push_compacted(buffer: &mut Vec<u8>, position: &mut usize, bytes: &[u8]) {
+    if *position > 0 {
+        buffer.drain(..*position);
+        *position = 0;
+    }
+    buffer.extend_from_slice(bytes);
+}
+
+/// Incremental decoder fed the chunks of a response body as they arrive.
+pub(crate) trait ChunkDecoder {
+    type Item;
+
+    fn push(&mut self, bytes: &[u8]);
+
+    /// Next complete item, or `None` until more data arrives.
+    fn next_item(&mut self) -> Result<Option<Self::Item>>;
+
+    /// Checks that the body didn't end part way through an item.
+    fn finish(&self) -> Result<()>;
+}
+
+/// Response body decoded into items as it is received. Decoding stops after
+/// the first error.
+pub(crate) struct DecodedBody<D> {
+    body: BoxStream<'static, reqwest::Result<Bytes>>,
+    pub(crate) decoder: D,
+    done: bool,
+    failed: bool,
+}
+
+impl<D: ChunkDecoder> DecodedBody<D> {
+    pub(crate) fn new(body: BoxStream<'static, reqwest::Result<Bytes>>, decoder: D) -> Self {
+        DecodedBody {
+            body,
+            decoder,
+            done: false,
+            failed: false,
+        }
+    }
+
+    pub(crate) fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<D::Item>>> {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match self.decoder.next_item() {
+                Ok(Some(item)) => return Poll::Ready(Some(Ok(item))),
+                Ok(None) => {}
+                Err(e) => {
+                    self.failed = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            match ready!(self.body.as_mut().poll_next(cx)) {
+                Some(Ok(bytes)) => self.decoder.push(&bytes),
+                Some(Err(e)) => {
+                    self.failed = true;
+                    return Poll::Ready(Some(Err(Error::from(e))));
+                }
+                None => {
+                    self.done = true;
+                    if let Err(e) = self.decoder.finish() {
+                        self.failed = true;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Running totals reported while a download is written to disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DownloadProgress {
+    pub bytes_received: u64,
+    pub records_decoded: u64,
+}
+
+/// Incremental decoder for an mbn byte stream (metadata followed by records)
+/// that arrives in arbitrarily sized chunks. Records split across chunks are
+/// held until the remaining bytes are pushed.
+#[derive(Debug, Default)]
+pub struct RecordBuffer {
+    buffer: Vec<u8>,
+    position: usize,
+    metadata: Option<Metadata>,
+    metadata_read: bool,
+}
+
+impl RecordBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffer for a stream that contains records only, without metadata.
+    pub fn without_metadata() -> Self {
+        RecordBuffer {
+            metadata_read: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        push_compacted(&mut self.buffer, &mut self.position, bytes);
+    }
+
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// Number of buffered bytes not yet returned as a record.
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    /// Moves the unread bytes so the next record starts on a `RecordHeader`
+    /// boundary and can be decoded without copying it. Record sizes are
+    /// multiples of the alignment, so this happens at most once per push.
+    fn align_position(&mut self) {
+        if is_aligned(&self.buffer[self.position..]) {
+            return;
+        }
+
+        let remaining = self.remaining();
+        // Reserve first, growing may move the allocation and change its alignment
+        self.buffer.reserve(RECORD_ALIGN);
+        let target = self.buffer.as_ptr().align_offset(RECORD_ALIGN);
+        if target + remaining > self.buffer.len() {
+            self.buffer.resize(target + remaining, 0);
+        }
+        self.buffer
+            .copy_within(self.position..self.position + remaining, target);
+        self.buffer.truncate(target + remaining);
+        self.position = target;
+    }
+
+    fn read_metadata(&mut self) -> Result<bool> {
+        if self.metadata_read {
+            return Ok(true);
+        }
+
+        match read_metadata(&self.buffer[self.position..])? {
+            Some((metadata, consumed)) => {
+                self.metadata = Some(metadata);
+                self.metadata_read = true;
+                self.position += consumed;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Returns the raw bytes of the next complete record, or `None` until more data arrives.
+    pub fn next_record_bytes(&mut self) -> Result<Option<&[u8]>> {
+        if !self.read_metadata()? {
+            return Ok(None);
+        }
+
+        let available = &self.buffer[self.position..];
+        let size = match read_header(available) {
+            Some(header) => header.record_size(),
+            None => return Ok(None),
+        };
+
+        if size < mem::size_of::<RecordHeader>() {
+            return Err(Error::DecodeError(format!(
+                "Invalid record length {}",
+                size
+            )));
+        }
+        if available.len() < size {
+            return Ok(None);
+        }
+
+        self.align_position();
+        let start = self.position;
+        self.position += size;
+        Ok(Some(&self.buffer[start..start + size]))
+    }
+
+    /// Returns the next complete record, or `None` until more data arrives.
+    pub fn next_record(&mut self) -> Result<Option<RecordEnum>> {
+        match self.next_record_bytes()? {
+            Some(bytes) => decode_record(bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Checks that the stream didn't end part way through a record.
+    pub fn finish(&self) -> Result<()> {
+        match self.remaining() {
+            0 => Ok(()),
+            n => Err(Error::DecodeError(format!(
+                "Stream ended with {} bytes of incomplete record",
+                n
+            ))),
+        }
+    }
+}
+
+impl ChunkDecoder for RecordBuffer {
+    type Item = RecordEnum;
+
+    fn push(&mut self, bytes: &[u8]) {
+        RecordBuffer::push(self, bytes)
+    }
+
+    fn next_item(&mut self) -> Result<Option<RecordEnum>> {
+        self.next_record()
+    }
+
+    fn finish(&self) -> Result<()> {
+        RecordBuffer::finish(self)
+    }
+}
+
+/// Stream of records decoded from a response body as it is received.
+pub struct RecordStream {
+    body: DecodedBody<RecordBuffer>,
+}
+
+impl RecordStream {
+    pub(crate) fn new(body: BoxStream<'static, reqwest::Result<Bytes>>) -> Self {
+        RecordStream {
+            body: DecodedBody::new(body, RecordBuffer::new()),
+        }
+    }
+
+    /// Metadata from the start of the response, available once the first records are polled.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.body.decoder.metadata()
+    }
+}
+
+impl Stream for RecordStream {
+    type Item = Result<RecordEnum>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.body.poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::historical::{Historical, RetrieveParams};
+    use futures_util::StreamExt;
+    use mbn::encode::RecordEncoder;
+    use mbn::enums::Schema;
+    use mbn::records::{BidAskPair, Mbp1Msg};
+    use mbn::symbols::SymbolMap;
+    use serde_json::json;
+
+    pub(crate) fn mbp(instrument_id: u32, ts: u64) -> Mbp1Msg {
+        Mbp1Msg {
+            hd: RecordHeader::new::<Mbp1Msg>(instrument_id, ts),
+            price: 6770,
+            size: 1,
+            action: 1,
+            side: 2,
+            depth: 0,
+            flags: 0,
+            ts_recv: ts,
+            ts_in_delta: 17493,
+            sequence: 739763,
+            discriminator: 0,
+            levels: [BidAskPair {
+                ask_px: 1,
+                bid_px: 1,
+                bid_sz: 2,
+                ask_sz: 2,
+                bid_ct: 10,
+                ask_ct: 20,
+            }],
+        }
+    }
+
+    /// `Mbp1` request for AAPL9 over `[start_ts, end_ts)`.
+    pub(crate) fn params(start_ts: i64, end_ts: i64) -> RetrieveParams {
+        RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts,
+            end_ts,
+            schema: Schema::Mbp1.to_string(),
+        }
+    }
+
+    /// Metadata followed by `records`, as returned by `mbp/get`.
+    pub(crate) fn encoded(records: &[Mbp1Msg]) -> Vec<u8> {
+        let mut mappings = SymbolMap::new();
+        mappings.add_instrument("AAPL9", 1);
+        let metadata = Metadata::new(Schema::Mbp1, 0, u64::MAX, mappings);
+
+        let mut buffer = write_metadata(&metadata);
+        encode_into(&mut buffer, records);
+        buffer
+    }
+
+    /// `records` without metadata, as sent to `mbp/create`.
+    pub(crate) fn records(records: &[Mbp1Msg]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        encode_into(&mut buffer, records);
+        buffer
+    }
+
+    fn encode_into(buffer: &mut Vec<u8>, records: &[Mbp1Msg]) {
+        let refs: Vec<RecordRef> = records.iter().map(|r| r.into()).collect();
+        let mut encoder = RecordEncoder::new(buffer);
+        encoder.encode_records(&refs).expect("Encoding failed");
+    }
+
+    #[test]
+    fn test_metadata_round_trip() -> Result<()> {
+        let bytes = encoded(&[]);
+
+        // Test
+        let (metadata, consumed) = read_metadata(&bytes)?.expect("Metadata incomplete");
+
+        // Validate
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(metadata.schema, Schema::Mbp1);
+        assert!(read_metadata(&bytes[..bytes.len() - 1])?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_split_across_chunks() -> Result<()> {
+        let records = vec![mbp(1, 1), mbp(1, 2), mbp(1, 3)];
+        let bytes = encoded(&records);
+
+        // Test every chunk size, so records are split at every offset
+        for chunk_size in 1..bytes.len() {
+            let mut buffer = RecordBuffer::new();
+            let mut decoded = Vec::new();
+
+            for chunk in bytes.chunks(chunk_size) {
+                buffer.push(chunk);
+                while let Some(record) = buffer.next_record()? {
+                    decoded.push(record);
+                }
+            }
+            buffer.finish()?;
+
+            // Validate
+            let expected: Vec<RecordEnum> = records.iter().map(|r| RecordEnum::Mbp1(*r)).collect();
+            assert_eq!(decoded, expected);
+            assert!(buffer.metadata().is_some());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncated_record() -> Result<()> {
+        let bytes = encoded(&[mbp(1, 1)]);
+        let mut buffer = RecordBuffer::new();
+
+        // Test
+        buffer.push(&bytes[..bytes.len() - 3]);
+        let record = buffer.next_record()?;
+
+        // Validate
+        assert!(record.is_none());
+        assert!(matches!(buffer.finish(), Err(Error::DecodeError(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_record_checks_length() -> Result<()> {
+        let bytes = encoded(&[mbp(1, 1)]);
+        let mut buffer = RecordBuffer::new();
+        buffer.push(&bytes);
+
+        // Test
+        let record = buffer.next_record_bytes()?.expect("Record incomplete");
+
+        // Validate
+        assert!(is_aligned(record));
+        assert!(decode_record(record).is_ok());
+        assert!(matches!(
+            decode_record(&record[..record.len() - RECORD_ALIGN]),
+            Err(Error::DecodeError(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_records() -> Result<()> {
+        let records = vec![mbp(1, 1), mbp(1, 2)];
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(200)
+            .with_body(encoded(&records))
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let params = RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts: 0,
+            end_ts: 10,
+            schema: Schema::Mbp1.to_string(),
+        };
+
+        // Test
+        let mut stream = client.stream_records(&params).await?;
+        let mut decoded = Vec::new();
+        while let Some(record) = stream.next().await {
+            decoded.push(record?);
+        }
+
+        // Validate
+        assert_eq!(decoded.len(), 2);
+        assert!(stream.metadata().is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_stream_records_error() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/mbp/get")
+            .with_status(404)
+            .with_body(json!({"status": "failed", "message": "No data", "code": 404}).to_string())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let params = RetrieveParams {
+            symbols: vec!["AAPL9".to_string()],
+            start_ts: 0,
+            end_ts: 10,
+            schema: Schema::Mbp1.to_string(),
+        };
+
+        // Test
+        let result = client.stream_records(&params).await;
+
+        // Validate
+        assert!(matches!(result, Err(Error::NotFound(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use crate::error::Result;
+use crate::historical::Historical;
+use crate::search::InstrumentQuery;
+use crate::stream::header;
+use mbn::record_enum::RecordEnum;
+use mbn::symbols::Instrument;
+use std::collections::HashMap;
+
+/// Client-side cache of the instrument universe, mapping tickers to instrument
+/// ids and back without a request per lookup.
+#[derive(Debug, Clone, Default)]
+pub struct InstrumentCache {
+    instruments: HashMap<u32, Instrument>,
+    ids: HashMap<String, u32>,
+}
+
+impl InstrumentCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the cache from a `list_symbols` response. Instruments without an
+    /// id are skipped.
+    pub fn from_instruments(instruments: Vec<Instrument>) -> Self {
+        let mut cache = InstrumentCache::new();
+        for instrument in instruments {
+            cache.insert(instrument);
+        }
+        cache
+    }
+
+    /// Replaces the cached universe with the current `list_symbols` response.
+    pub async fn refresh(&mut self, client: &Historical) -> Result<()> {
+        let instruments = client.list_symbols().await?.into_result()?;
+        *self = InstrumentCache::from_instruments(instruments);
+        Ok(())
+    }
+
+    pub fn insert(&mut self, instrument: Instrument) {
+        if let Some(id) = instrument.instrument_id {
+            if let Some(previous) = self.instruments.get(&id) {
+                self.ids.remove(&previous.ticker);
+            }
+            self.ids.insert(instrument.ticker.clone(), id);
+            self.instruments.insert(id, instrument);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.instruments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instruments.is_empty()
+    }
+
+    pub fn instrument_id(&self, ticker: &str) -> Option<u32> {
+        self.ids.get(ticker).copied()
+    }
+
+    pub fn ticker(&self, instrument_id: u32) -> Option<&str> {
+        self.instruments
+            .get(&instrument_id)
+            .map(|instrument| instrument.ticker.as_str())
+    }
+
+    pub fn instrument(&self, instrument_id: u32) -> Option<&Instrument> {
+        self.instruments.get(&instrument_id)
+    }
+
+    /// Cached instruments matching `query`, without a request.
+    pub fn search<'a>(
+        &'a self,
+        query: &'a InstrumentQuery,
+    ) -> impl Iterator<Item = &'a Instrument> + 'a {
+        self.instruments
+            .values()
+            .filter(move |instrument| query.matches(instrument))
+    }
+
+    /// Ticker of the instrument a decoded record belongs to.
+    pub fn resolve(&self, record: &RecordEnum) -> Option<&str> {
+        self.ticker(header(record).instrument_id)
+    }
+
+    /// Mappings in the form used by mbn metadata, e.g. for `Exporter::with_mappings`.
+    pub fn to_mappings(&self) -> mbn::symbols::SymbolMap {
+        let mut mappings = mbn::symbols::SymbolMap::new();
+        for (ticker, id) in &self.ids {
+            mappings.add_instrument(ticker, *id);
+        }
+        mappings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::tests::mbp;
+    use mbn::symbols::Vendors;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn instrument(id: u32, ticker: &str) -> Instrument {
+        Instrument::new(
+            Some(id),
+            ticker,
+            "Tester",
+            Vendors::Databento,
+            Some("continuous".to_string()),
+            Some("GLBX.MDP3".to_string()),
+            1,
+            1,
+            true,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_instrument_cache_refresh() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": [instrument(1, "AAPL9"), instrument(2, "HE.n.0")],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let mut cache = InstrumentCache::new();
+        cache.refresh(&client).await?;
+
+        // Validate
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.instrument_id("HE.n.0"), Some(2));
+        assert_eq!(cache.ticker(1), Some("AAPL9"));
+        assert_eq!(cache.resolve(&RecordEnum::Mbp1(mbp(2, 1))), Some("HE.n.0"));
+        assert_eq!(cache.resolve(&RecordEnum::Mbp1(mbp(3, 1))), None);
+        let query = InstrumentQuery::new().ticker_prefix("HE");
+        let found: Vec<&str> = cache.search(&query).map(|i| i.ticker.as_str()).collect();
+        assert_eq!(found, vec!["HE.n.0"]);
+        assert_eq!(
+            cache.to_mappings().get_instrument_ticker(1),
+            Some("AAPL9".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_instrument_cache_rename() {
+        let mut cache = InstrumentCache::from_instruments(vec![instrument(1, "AAPL9")]);
+
+        // Test
+        cache.insert(instrument(1, "AAPL"));
+
+        // Validate
+        assert_eq!(cache.instrument_id("AAPL9"), None);
+        assert_eq!(cache.instrument_id("AAPL"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_get_instrument() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _mock = server
+            .mock("GET", "/historical/instruments/get_instrument")
+            .match_query(Matcher::UrlEncoded("ticker".into(), "HE.n.0".into()))
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": instrument(2, "HE.n.0"),
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let response = client.get_instrument("HE.n.0").await?;
+
+        // Validate
+        let instrument = response.data.expect("Expected an instrument");
+        assert_eq!(instrument.instrument_id, Some(2));
+        assert_eq!(instrument.ticker, "HE.n.0");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_instrument_fallback() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _get = server
+            .mock("GET", "/historical/instruments/get_instrument")
+            .match_query(Matcher::Any)
+            .with_status(404)
+            .create_async()
+            .await;
+        let _list = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(200)
+            .with_body(
+                json!({
+                    "status": "success",
+                    "message": "",
+                    "code": 200,
+                    "data": [instrument(1, "AAPL9"), instrument(2, "HE.n.0")],
+                })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let found = client.get_instrument("HE.n.0").await?;
+        let missing = client.get_instrument("ZC.n.0").await?;
+
+        // Validate
+        assert_eq!(found.data.and_then(|i| i.instrument_id), Some(2));
+        assert!(missing.data.is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{error::Error, error::Result};
+use mbn::symbols::{Instrument, Vendors};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct InstrumentFile {
+    instruments: Vec<Instrument>,
+}
+
+/// Reads the desired instrument universe from a `.toml` (an `[[instruments]]`
+/// array), `.csv` (one instrument per row) or `.json` (an array) file.
+pub fn load_instruments<P: AsRef<Path>>(path: P) -> Result<Vec<Instrument>> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("toml") => {
+            let file: InstrumentFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+            Ok(file.instruments)
+        }
+        Some("csv") => {
+            let mut reader = csv::Reader::from_path(path)?;
+            let instruments = reader.deserialize().collect::<csv::Result<_>>()?;
+            Ok(instruments)
+        }
+        Some("json") => Ok(serde_json::from_slice(&std::fs::read(path)?)?),
+        _ => Err(Error::CustomError(format!(
+            "Unsupported instrument file '{}', expected .toml, .csv or .json",
+            path.display()
+        ))),
+    }
+}
+
+/// Instrument fields compared when deciding whether an update is needed.
+/// The id and the availability range are maintained by the server, so they
+/// never call for an update.
+fn fields(instrument: &Instrument) -> (&str, &Vendors, Option<&str>, Option<&str>, bool) {
+    (
+        instrument.name.as_str(),
+        &instrument.vendor,
+        instrument.stype.as_deref(),
+        instrument.dataset.as_deref(),
+        instrument.active,
+    )
+}
+
+/// Identity of an instrument, since tickers are only unique per vendor.
+fn key(instrument: &Instrument) -> (String, &str) {
+    (instrument.vendor.to_string(), instrument.ticker.as_str())
+}
+
+/// Id of an instrument listed by the server, which every listed instrument
+/// should have.
+fn instrument_id(instrument: &Instrument) -> Result<i32> {
+    let id = instrument.instrument_id.ok_or_else(|| {
+        Error::CustomError(format!("Instrument '{}' has no id", instrument.ticker))
+    })?;
+
+    i32::try_from(id).map_err(|_| {
+        Error::CustomError(format!(
+            "Instrument id {} of '{}' is out of range",
+            id, instrument.ticker
+        ))
+    })
+}
+
+/// Changes needed to bring the server's instruments in line with a file.
+#[derive(Debug, Clone, Default)]
+pub struct SyncPlan {
+    pub create: Vec<Instrument>,
+    /// Instruments whose fields differ, with the id of the existing entry.
+    pub update: Vec<(i32, Instrument)>,
+    /// Active instruments missing from the file, already marked inactive.
+    pub deactivate: Vec<(i32, Instrument)>,
+}
+
+impl SyncPlan {
+    /// Diffs `desired` against `current` by vendor and ticker. Every
+    /// instrument in `current` that needs a change must have an id.
+    pub fn new(desired: &[Instrument], current: &[Instrument]) -> Result<Self> {
+        let existing: HashMap<_, &Instrument> = current
+            .iter()
+            .map(|instrument| (key(instrument), instrument))
+            .collect();
+        let listed: HashSet<_> = desired.iter().map(key).collect();
+        let mut plan = SyncPlan::default();
+
+        for instrument in desired {
+            match existing.get(&key(instrument)) {
+                None => plan.create.push(instrument.clone()),
+                Some(current) => {
+                    if fields(instrument) != fields(current) {
+                        plan.update
+                            .push((instrument_id(current)?, instrument.clone()));
+                    }
+                }
+            }
+        }
+
+        for instrument in current {
+            if !listed.contains(&key(instrument)) && instrument.active {
+                let mut inactive = instrument.clone();
+                inactive.active = false;
+                plan.deactivate.push((instrument_id(instrument)?, inactive));
+            }
+        }
+
+        Ok(plan)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.update.is_empty() && self.deactivate.is_empty()
+    }
+}
+
+/// Dry run listing of the plan, one change per line.
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instrument in &self.create {
+            writeln!(f, "create     {}", instrument.ticker)?;
+        }
+        for (id, instrument) in &self.update {
+            writeln!(f, "update     {} (id {})", instrument.ticker, id)?;
+        }
+        for (id, instrument) in &self.deactivate {
+            writeln!(f, "deactivate {} (id {})", instrument.ticker, id)?;
+        }
+        write!(
+            f,
+            "{} to create, {} to update, {} to deactivate",
+            self.create.len(),
+            self.update.len(),
+            self.deactivate.len()
+        )
+    }
+}
+
+/// Outcome of applying a `SyncPlan`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    pub created: usize,
+    pub updated: usize,
+    pub deactivated: usize,
+    /// Tickers that couldn't be changed, with the server's message or the
+    /// request's error.
+    pub failed: Vec<(String, String)>,
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} created, {} updated, {} deactivated, {} failed",
+            self.created,
+            self.updated,
+            self.deactivated,
+            self.failed.len()
+        )?;
+        for (ticker, message) in &self.failed {
+            write!(f, "\n  {}: {}", ticker, message)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use mbn::symbols::Vendors;
+    use mockito::Matcher;
+    use serde_json::json;
+
+    fn instrument(id: Option<u32>, ticker: &str, name: &str) -> Instrument {
+        Instrument::new(
+            id,
+            ticker,
+            name,
+            Vendors::Databento,
+            Some("continuous".to_string()),
+            Some("GLBX.MDP3".to_string()),
+            1,
+            1,
+            true,
+        )
+    }
+
+    #[test]
+    fn test_sync_plan() -> Result<()> {
+        let desired = vec![
+            instrument(None, "AAPL9", "Apple"),
+            instrument(None, "HE.n.0", "Lean hogs"),
+            instrument(None, "ZC.n.0", "Corn"),
+        ];
+        let current = vec![
+            instrument(Some(1), "AAPL9", "Apple"),
+            instrument(Some(2), "HE.n.0", "Hogs"),
+            instrument(Some(3), "TSLA", "Tesla"),
+        ];
+
+        // Test
+        let plan = SyncPlan::new(&desired, &current)?;
+
+        // Validate
+        assert_eq!(plan.create.len(), 1);
+        assert_eq!(plan.create[0].ticker, "ZC.n.0");
+        assert_eq!(plan.update.len(), 1);
+        assert_eq!(plan.update[0].0, 2);
+        assert_eq!(plan.update[0].1.name, "Lean hogs");
+        assert_eq!(plan.deactivate.len(), 1);
+        assert_eq!(plan.deactivate[0].0, 3);
+        assert!(!plan.deactivate[0].1.active);
+        assert!(plan
+            .to_string()
+            .ends_with("1 to create, 1 to update, 1 to deactivate"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_plan_settles() -> Result<()> {
+        let desired = vec![instrument(None, "AAPL9", "Apple")];
+        // Id and availability filled in by the server
+        let current = vec![Instrument::new(
+            Some(1),
+            "AAPL9",
+            "Apple",
+            Vendors::Databento,
+            Some("continuous".to_string()),
+            Some("GLBX.MDP3".to_string()),
+            1704067200000000000,
+            1700000000000000000,
+            true,
+        )];
+
+        // Test
+        let plan = SyncPlan::new(&desired, &current)?;
+
+        // Validate
+        assert!(plan.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_plan_missing_id() {
+        let desired = vec![instrument(None, "HE.n.0", "Lean hogs")];
+        let current = vec![instrument(None, "HE.n.0", "Hogs")];
+
+        // Test
+        let result = SyncPlan::new(&desired, &current);
+
+        // Validate
+        assert!(matches!(result, Err(Error::CustomError(_))));
+    }
+
+    #[test]
+    fn test_load_instruments_json() -> Result<()> {
+        let path = std::env::temp_dir().join("midas_test_instruments.json");
+        let instruments = vec![instrument(None, "AAPL9", "Apple")];
+        std::fs::write(&path, serde_json::to_vec(&instruments)?)?;
+
+        // Test
+        let loaded = load_instruments(&path)?;
+
+        // Validate
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].ticker, "AAPL9");
+        assert!(matches!(
+            load_instruments("instruments.xlsx"),
+            Err(Error::CustomError(_))
+        ));
+
+        // Cleanup
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_instruments_toml() -> Result<()> {
+        let path = std::env::temp_dir().join("midas_test_instruments.toml");
+        let instruments = vec![
+            instrument(None, "AAPL9", "Apple"),
+            instrument(None, "HE.n.0", "Lean hogs"),
+        ];
+        let file = InstrumentFile {
+            instruments: instruments.clone(),
+        };
+        std::fs::write(&path, toml::to_string(&file).expect("Serializing failed"))?;
+
+        // Test
+        let loaded = load_instruments(&path)?;
+
+        // Validate
+        assert_eq!(
+            serde_json::to_value(&loaded)?,
+            serde_json::to_value(&instruments)?
+        );
+
+        // Cleanup
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_instruments_csv() -> Result<()> {
+        let path = std::env::temp_dir().join("midas_test_instruments.csv");
+        let instruments = vec![
+            instrument(None, "AAPL9", "Apple"),
+            instrument(None, "HE.n.0", "Lean hogs"),
+        ];
+        let mut writer = csv::Writer::from_path(&path)?;
+        for instrument in &instruments {
+            writer.serialize(instrument)?;
+        }
+        writer.flush()?;
+
+        // Test
+        let loaded = load_instruments(&path)?;
+
+        // Validate
+        assert_eq!(
+            serde_json::to_value(&loaded)?,
+            serde_json::to_value(&instruments)?
+        );
+
+        // Cleanup
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_apply_sync_plan() -> Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _create = server
+            .mock("POST", "/historical/instruments/create")
+            .match_body(Matcher::Regex("Corn".to_string()))
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": 4}).to_string(),
+            )
+            .create_async()
+            .await;
+        let _malformed = server
+            .mock("POST", "/historical/instruments/create")
+            .match_body(Matcher::Regex("Wheat".to_string()))
+            .with_status(502)
+            .with_body("Bad gateway")
+            .create_async()
+            .await;
+        let _update = server
+            .mock("PUT", "/historical/instruments/update")
+            .match_body(Matcher::Regex("Lean hogs".to_string()))
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": ""}).to_string(),
+            )
+            .create_async()
+            .await;
+        let _deactivate = server
+            .mock("PUT", "/historical/instruments/update")
+            .match_body(Matcher::Regex("Tesla".to_string()))
+            .with_status(404)
+            .with_body(
+                json!({"status": "failed", "message": "Not found", "code": 404, "data": ""})
+                    .to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+        let plan = SyncPlan {
+            create: vec![
+                instrument(None, "ZC.n.0", "Corn"),
+                instrument(None, "ZW.n.0", "Wheat"),
+            ],
+            update: vec![(2, instrument(None, "HE.n.0", "Lean hogs"))],
+            deactivate: vec![(3, instrument(Some(3), "TSLA", "Tesla"))],
+        };
+
+        // Test
+        let report = client.apply_instrument_sync(&plan).await?;
+
+        // Validate
+        assert_eq!(report.created, 1);
+        assert_eq!(report.updated, 1);
+        assert_eq!(report.deactivated, 0);
+        assert_eq!(report.failed.len(), 2);
+        assert_eq!(report.failed[0].0, "ZW.n.0");
+        assert_eq!(
+            report.failed[1],
+            ("TSLA".to_string(), "Not found".to_string())
+        );
+        Ok(())
+    }
+}
@@ -1,31 +1,93 @@
-use crate::response::ApiResponse;
-use crate::{error::Error, error::Result};
+use crate::auth::Auth;
+use crate::client::DEFAULT_TIMEOUT;
+use crate::error::Result;
+use crate::progress::ProgressStream;
+use crate::response::{ApiResponse, Created};
+use crate::retry::{self, RetryPolicy};
+use crate::upload::{UploadEncoding, UploadTransport};
 use futures_util::StreamExt;
 use mbn::backtest_encode::BacktestEncoder;
 use mbn::{backtest::BacktestData, live::LiveData};
-use reqwest::StatusCode;
-use reqwest::{self, Client, ClientBuilder};
-use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::{self, Client, ClientBuilder, RequestBuilder};
+use reqwest::{Response, StatusCode};
+use std::future::Future;
 
 #[derive(Clone)]
 pub struct Trading {
     base_url: String,
     client: Client,
+    auth: Option<Auth>,
+    retry: RetryPolicy,
+    upload: UploadTransport,
 }
 
 impl Trading {
     pub fn new(base_url: &str) -> Self {
         let client = ClientBuilder::new()
-            .timeout(Duration::from_secs(20000)) // Set timeout to 120 seconds
+            .timeout(DEFAULT_TIMEOUT)
             .build()
             .expect("Failed to build HTTP client");
 
+        Trading::with_client(base_url, client)
+    }
+
+    /// Reuses an existing HTTP client, e.g. the pool owned by a `MidasClient`.
+    pub fn with_client(base_url: &str, client: Client) -> Self {
         Trading {
             base_url: base_url.to_string(),
             client,
+            auth: None,
+            retry: RetryPolicy::default(),
+            upload: UploadTransport::default(),
         }
     }
 
+    /// Attaches credentials applied to every request.
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Wire format of uploaded records, JSON by default. Binary forms fall
+    /// back to JSON if the server answers `415 Unsupported Media Type`.
+    pub fn with_upload_encoding(mut self, encoding: UploadEncoding) -> Self {
+        self.upload = UploadTransport::new(encoding);
+        self
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        retry::send(request, self.auth.as_ref(), &self.retry).await
+    }
+
+    /// `send` for GETs that read the whole response, re-sending the request if
+    /// the body fails part way through.
+    async fn send_and_read<T, F, Fut>(&self, request: RequestBuilder, read: F) -> Result<T>
+    where
+        F: FnMut(Response) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        retry::send_and_read(request, self.auth.as_ref(), &self.retry, read).await
+    }
+
+    async fn post_records(&self, url: &str, data: &[u8], headers: HeaderMap) -> Result<Response> {
+        self.upload
+            .post(
+                &self.client,
+                url,
+                data,
+                headers,
+                self.auth.as_ref(),
+                &self.retry,
+            )
+            .await
+    }
+
     fn url(&self, endpoint: &str) -> String {
         format!(
             "{}{}{}",
@@ -36,37 +98,27 @@ impl Trading {
     }
 
     // Live
-    pub async fn create_live(&self, data: &LiveData) -> Result<ApiResponse<i32>> {
+    pub async fn create_live(&self, data: &LiveData) -> Result<ApiResponse<Option<Created>>> {
         let url = self.url("live/create");
-        let response = self.client.post(&url).json(data).send().await?;
+        let response = self.send(self.client.post(&url).json(data)).await?;
 
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<i32>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<i32>::from_response(response).await?;
-        Ok(api_response)
+        // Deserialize the API response and return it, even if it indicates failure
+        let api_response = ApiResponse::<Option<i32>>::from_response(response).await?;
+        Ok(api_response.into_created())
     }
 
     pub async fn list_live(&self) -> Result<ApiResponse<Vec<(i32, String)>>> {
         let url = self.url("live/list");
-        let response = self.client.get(&url).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<(i32, String)>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<(i32, String)>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url),
+            ApiResponse::<Vec<(i32, String)>>::from_response,
+        )
+        .await
     }
 
     pub async fn delete_live(&self, id: &i32) -> Result<ApiResponse<String>> {
         let url = self.url("live/delete");
-        let response = self.client.delete(&url).json(id).send().await?;
+        let response = self.send(self.client.delete(&url).json(id)).await?;
 
         if response.status() != StatusCode::OK {
             // Deserialize the API response and return it, even if it indicates failure
@@ -79,20 +131,15 @@ impl Trading {
 
     pub async fn get_live(&self, id: &i32) -> Result<ApiResponse<Vec<LiveData>>> {
         let url = self.url(&format!("live/get?id={}", id));
-        let response = self.client.get(&url).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<LiveData>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<LiveData>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url),
+            ApiResponse::<Vec<LiveData>>::from_response,
+        )
+        .await
     }
 
     // Backtest
-    pub async fn create_backtest(&self, backtest: &BacktestData) -> Result<ApiResponse<String>> {
+    async fn post_backtest(&self, backtest: &BacktestData) -> Result<Response> {
         let mut bytes = Vec::new();
         let mut encoder = BacktestEncoder::new(&mut bytes);
         encoder.encode_metadata(&backtest.metadata);
@@ -102,7 +149,11 @@ impl Trading {
         encoder.encode_signals(&backtest.signals);
 
         let url = self.url("backtest/create");
-        let response = self.client.post(&url).json(&bytes).send().await?;
+        self.post_records(&url, &bytes, HeaderMap::new()).await
+    }
+
+    pub async fn create_backtest(&self, backtest: &BacktestData) -> Result<ApiResponse<String>> {
+        let response = self.post_backtest(backtest).await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
@@ -110,36 +161,20 @@ impl Trading {
             return ApiResponse::<String>::from_response(response).await;
         }
 
-        let mut stream = response.bytes_stream();
+        let mut messages = ProgressStream::new(response);
         let mut last_response: Vec<ApiResponse<String>> = Vec::new();
 
-        // Output the streamed response directly to the user
-        while let Some(chunk) = stream.next().await {
-            match chunk {
-                Ok(bytes) => {
-                    let bytes_str = String::from_utf8_lossy(&bytes);
-                    match serde_json::from_str::<ApiResponse<String>>(&bytes_str) {
-                        Ok(response) => {
-                            if response.status != "success" {
-                                return Ok(response);
-                            }
-
-                            if last_response.is_empty() {
-                                last_response.push(response);
-                            } else {
-                                last_response[0] = response;
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("Error while receiving chunk: {:?}", e);
-                            return Err(Error::from(e));
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error while reading chunk: {:?}", e);
-                    return Err(Error::from(e));
-                }
+        // Keep the latest progress message, stopping at the first failure
+        while let Some(message) = messages.next().await {
+            let response = message?;
+            if response.status != "success" {
+                return Ok(response);
+            }
+
+            if last_response.is_empty() {
+                last_response.push(response);
+            } else {
+                last_response[0] = response;
             }
         }
 
@@ -155,23 +190,25 @@ impl Trading {
         }
     }
 
+    /// Uploads a backtest, returning the messages the server streams back as
+    /// a `Stream`.
+    pub async fn create_backtest_stream(&self, backtest: &BacktestData) -> Result<ProgressStream> {
+        let response = self.post_backtest(backtest).await?;
+        ProgressStream::from_response(response).await
+    }
+
     pub async fn list_backtest(&self) -> Result<ApiResponse<Vec<(i32, String)>>> {
         let url = self.url("backtest/list");
-        let response = self.client.get(&url).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<(i32, String)>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<(i32, String)>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url),
+            ApiResponse::<Vec<(i32, String)>>::from_response,
+        )
+        .await
     }
 
     pub async fn delete_backtest(&self, id: &i32) -> Result<ApiResponse<String>> {
         let url = self.url("backtest/delete");
-        let response = self.client.delete(&url).json(id).send().await?;
+        let response = self.send(self.client.delete(&url).json(id)).await?;
 
         // Check for HTTP status
         if response.status() != StatusCode::OK {
@@ -185,16 +222,11 @@ impl Trading {
 
     pub async fn get_backtest(&self, id: &i32) -> Result<ApiResponse<Vec<BacktestData>>> {
         let url = self.url(&format!("backtest/get?id={}", id));
-        let response = self.client.get(&url).send().await?;
-
-        // Check for HTTP status
-        if response.status() != StatusCode::OK {
-            // Deserialize the API response and return it, even if it indicates failure
-            return ApiResponse::<Vec<BacktestData>>::from_response(response).await;
-        }
-
-        let api_response = ApiResponse::<Vec<BacktestData>>::from_response(response).await?;
-        Ok(api_response)
+        self.send_and_read(
+            self.client.get(&url),
+            ApiResponse::<Vec<BacktestData>>::from_response,
+        )
+        .await
     }
 }
 
@@ -202,22 +234,9 @@ impl Trading {
 mod tests {
     use super::*;
     use dotenv::dotenv;
-    use regex::Regex;
     use serial_test::serial;
     use std::fs;
 
-    fn get_id_from_string(message: &str) -> Option<i32> {
-        let re = Regex::new(r"\d+$").unwrap();
-
-        if let Some(captures) = re.captures(message) {
-            if let Some(matched) = captures.get(0) {
-                let number: i32 = matched.as_str().parse().unwrap();
-                return Some(number);
-            }
-        }
-        None
-    }
-
     #[tokio::test]
     #[serial]
     // #[ignore]
@@ -328,7 +347,7 @@ mod tests {
         assert_eq!(response.status, "success");
 
         // Cleanup
-        let id = get_id_from_string(&response.message).expect("Error getting id from message.");
+        let id = response.data.expect("Expected an id").id;
         let _ = client.delete_live(&id).await?;
 
         Ok(())
@@ -349,7 +368,7 @@ mod tests {
             serde_json::from_str(&mock_data).expect("JSON was not well-formatted");
 
         let response = client.create_live(&live_data).await?;
-        let id = get_id_from_string(&response.message).expect("Error getting id from message.");
+        let id = response.data.expect("Expected an id").id;
 
         // Test
         let response = client.list_live().await?;
@@ -379,7 +398,7 @@ mod tests {
             serde_json::from_str(&mock_data).expect("JSON was not well-formatted");
 
         let response = client.create_live(&live_data).await?;
-        let id = get_id_from_string(&response.message).expect("Error getting id from message.");
+        let id = response.data.expect("Expected an id").id;
 
         // Test
         let response = client.get_live(&id).await?;
@@ -0,0 +1,278 @@
+use crate::auth::Auth;
+use crate::error::Result;
+use crate::retry::{self, RetryPolicy};
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use futures_util::stream;
+use reqwest::header::{HeaderMap, CONTENT_ENCODING, CONTENT_TYPE};
+use reqwest::{Body, Client, Response, StatusCode};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+
+/// Bytes read from disk per upload chunk, bounding the memory used by a
+/// file upload.
+pub const UPLOAD_CHUNK_SIZE: usize = 1 << 20;
+
+/// Opens `path` as a request body that reads `chunk_size` bytes at a time,
+/// returning it with the file length.
+pub(crate) async fn file_body(path: &str, chunk_size: usize) -> Result<(Body, u64)> {
+    let file = File::open(path).await?;
+    let length = file.metadata().await?.len();
+    let chunk_size = chunk_size.max(1);
+
+    let chunks = stream::try_unfold(file, move |mut file| async move {
+        let mut chunk = vec![0; chunk_size];
+        let read = file.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        chunk.truncate(read);
+        Ok(Some((Bytes::from(chunk), file)))
+    });
+
+    Ok((Body::wrap_stream(chunks), length))
+}
+
+/// Wire format of `create_mbp` and `create_backtest` bodies. JSON is the
+/// default, the binary forms need a server that accepts them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UploadEncoding {
+    /// JSON array of byte values, understood by every server version.
+    #[default]
+    Json,
+    /// Raw `application/octet-stream` body.
+    Binary,
+    /// Binary body compressed with gzip.
+    Gzip,
+    /// Binary body compressed with zstd.
+    Zstd,
+}
+
+impl UploadEncoding {
+    fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            UploadEncoding::Gzip => Some("gzip"),
+            UploadEncoding::Zstd => Some("zstd"),
+            UploadEncoding::Json | UploadEncoding::Binary => None,
+        }
+    }
+
+    /// Encodes `data` as the body sent for this encoding.
+    pub fn encode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            UploadEncoding::Json => Ok(serde_json::to_vec(data)?),
+            UploadEncoding::Binary => Ok(data.to_vec()),
+            UploadEncoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            UploadEncoding::Zstd => Ok(zstd::encode_all(data, 0)?),
+        }
+    }
+}
+
+/// Upload encoding shared by clones of a client. Servers that answer a binary
+/// upload with `415 Unsupported Media Type` are remembered, and later uploads
+/// go straight to the JSON form.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UploadTransport {
+    encoding: UploadEncoding,
+    json_only: Arc<AtomicBool>,
+}
+
+impl UploadTransport {
+    pub(crate) fn new(encoding: UploadEncoding) -> Self {
+        UploadTransport {
+            encoding,
+            json_only: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    fn encoding(&self) -> UploadEncoding {
+        if self.json_only.load(Ordering::Relaxed) {
+            UploadEncoding::Json
+        } else {
+            self.encoding
+        }
+    }
+
+    /// POSTs `data` to `url`, falling back to the JSON form if the server
+    /// doesn't accept binary bodies.
+    pub(crate) async fn post(
+        &self,
+        client: &Client,
+        url: &str,
+        data: &[u8],
+        headers: HeaderMap,
+        auth: Option<&Auth>,
+        policy: &RetryPolicy,
+    ) -> Result<Response> {
+        let encoding = self.encoding();
+        if encoding != UploadEncoding::Json {
+            let mut request = client
+                .post(url)
+                .headers(headers.clone())
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(encoding.encode(data)?);
+            if let Some(content_encoding) = encoding.content_encoding() {
+                request = request.header(CONTENT_ENCODING, content_encoding);
+            }
+
+            let response = retry::send(request, auth, policy).await?;
+            if response.status() != StatusCode::UNSUPPORTED_MEDIA_TYPE {
+                return Ok(response);
+            }
+            self.json_only.store(true, Ordering::Relaxed);
+        }
+
+        retry::send(client.post(url).headers(headers).json(data), auth, policy).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use crate::stream::tests::{encoded, mbp};
+    use flate2::read::GzDecoder;
+    use mockito::Matcher;
+    use serde_json::json;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn test_create_mbp_from_local_file() -> Result<()> {
+        let data = encoded(&[mbp(1, 1), mbp(1, 2), mbp(1, 3)]);
+        let path = std::env::temp_dir().join("midas_test_upload.bin");
+        let path = path.to_str().unwrap();
+        tokio::fs::write(path, &data).await?;
+
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/historical/mbp/upload")
+            .match_header("content-type", "application/octet-stream")
+            .match_body(Matcher::from(data.clone()))
+            .with_status(200)
+            .with_body(json!({"status": "success", "message": "Inserted 3 records", "code": 200, "data": ""}).to_string())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let response = client.create_mbp_from_local_file(path).await?;
+
+        // Validate
+        mock.assert_async().await;
+        assert_eq!(response.status, "success");
+
+        // Cleanup
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_upload_encoding_round_trip() -> Result<()> {
+        let data = encoded(&[mbp(1, 1), mbp(1, 2)]);
+
+        // Test
+        let gzip = UploadEncoding::Gzip.encode(&data)?;
+        let zstd = UploadEncoding::Zstd.encode(&data)?;
+        let json = UploadEncoding::Json.encode(&data)?;
+
+        // Validate
+        let mut decoded = Vec::new();
+        GzDecoder::new(gzip.as_slice()).read_to_end(&mut decoded)?;
+        assert_eq!(decoded, data);
+        assert_eq!(zstd::decode_all(zstd.as_slice())?, data);
+        assert_eq!(serde_json::from_slice::<Vec<u8>>(&json)?, data);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_binary() -> Result<()> {
+        let data = encoded(&[mbp(1, 1)]);
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header("content-type", "application/octet-stream")
+            .match_header("content-encoding", "zstd")
+            .match_body(Matcher::from(UploadEncoding::Zstd.encode(&data)?))
+            .with_status(200)
+            .with_body(json!({"status": "success", "message": "Inserted 1 records", "code": 200, "data": ""}).to_string())
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_upload_encoding(UploadEncoding::Zstd);
+
+        // Test
+        let response = client.create_mbp(&data).await?;
+
+        // Validate
+        mock.assert_async().await;
+        assert_eq!(response.status, "success");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_json_by_default() -> Result<()> {
+        let data = encoded(&[mbp(1, 1)]);
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(json!(data)))
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": ""}).to_string(),
+            )
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let response = client.create_mbp(&data).await?;
+
+        // Validate
+        mock.assert_async().await;
+        assert_eq!(response.status, "success");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_json_fallback() -> Result<()> {
+        let data = encoded(&[mbp(1, 1)]);
+        let mut server = mockito::Server::new_async().await;
+        let binary = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header("content-type", "application/octet-stream")
+            .with_status(415)
+            .expect(1)
+            .create_async()
+            .await;
+        let legacy = server
+            .mock("POST", "/historical/mbp/create")
+            .match_header("content-type", "application/json")
+            .match_body(Matcher::Json(json!(data)))
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": ""}).to_string(),
+            )
+            .expect(2)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url()).with_upload_encoding(UploadEncoding::Binary);
+
+        // Test
+        let first = client.create_mbp(&data).await?;
+        let second = client.clone().create_mbp(&data).await?;
+
+        // Validate
+        binary.assert_async().await;
+        legacy.assert_async().await;
+        assert_eq!(first.status, "success");
+        assert_eq!(second.status, "success");
+        Ok(())
+    }
+}
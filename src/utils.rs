@@ -1,50 +1,150 @@
 use crate::error::{Error, Result};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::path::Path;
+use tokio::fs;
 
+/// Values usable as a timestamp in nanoseconds since the Unix epoch.
+///
+/// Naive dates and datetimes are taken as UTC.
+pub trait IntoUnixNanos {
+    fn into_unix_nanos(self) -> Result<i64>;
+}
+
+impl IntoUnixNanos for i64 {
+    fn into_unix_nanos(self) -> Result<i64> {
+        Ok(self)
+    }
+}
+
+impl<Tz: TimeZone> IntoUnixNanos for DateTime<Tz> {
+    fn into_unix_nanos(self) -> Result<i64> {
+        self.timestamp_nanos_opt().ok_or_else(|| {
+            Error::InvalidDateFormat(format!(
+                "{} is out of range for nanosecond timestamps",
+                self.naive_utc()
+            ))
+        })
+    }
+}
+
+impl IntoUnixNanos for NaiveDateTime {
+    fn into_unix_nanos(self) -> Result<i64> {
+        self.and_utc().into_unix_nanos()
+    }
+}
+
+impl IntoUnixNanos for NaiveDate {
+    fn into_unix_nanos(self) -> Result<i64> {
+        self.and_hms_opt(0, 0, 0).unwrap().into_unix_nanos()
+    }
+}
+
+/// Naive formats accepted by `date_to_unix_nanos`, tried in order.
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+/// Parses a date or datetime into nanoseconds since the Unix epoch.
+///
+/// Accepts:
+/// - `YYYY-MM-DD` and `YYYY-MM-DD HH:MM[:SS[.fffffffff]]` (a `T` separator also works), taken as UTC
+/// - RFC 3339 with an offset, e.g. `2024-01-02T09:30:00.5-05:00`
+/// - any of the above followed by an IANA timezone, e.g. `2024-01-02 09:30 America/New_York`
 pub fn date_to_unix_nanos(date_str: &str) -> Result<i64> {
-    let naive_datetime = if date_str.len() == 10 {
-        // Attempt to parse date-only format YYYY-MM-DD
-        match NaiveDate::parse_from_str(date_str, "%Y-%m-%d") {
-            Ok(naive_date) => naive_date.and_hms_opt(0, 0, 0).unwrap(), // Set time to midnight
-            Err(_) => {
-                return Err(Error::InvalidDateFormat(format!(
-                    "Invalid date format '{}'. Expected format: YYYY-MM-DD",
-                    date_str
-                )));
-            }
-        }
-    } else {
-        // Attempt to parse datetime format YYYY-MM-DD HH:MM:SS
-        match NaiveDateTime::parse_from_str(date_str, "%Y-%m-%d %H:%M:%S") {
-            Ok(datetime) => datetime,
-            Err(_) => {
-                return Err(Error::InvalidDateFormat(format!(
-                    "Invalid datetime format '{}'. Expected format: YYYY-MM-DD HH:MM:SS",
-                    date_str
-                )));
-            }
+    let date_str = date_str.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date_str) {
+        return datetime.into_unix_nanos();
+    }
+
+    // Trailing IANA timezone
+    if let Some((local, tz)) = date_str.rsplit_once(' ') {
+        if let Ok(tz) = tz.parse::<Tz>() {
+            return local_to_unix_nanos(local, tz);
         }
-    };
+    }
 
-    // Convert the NaiveDateTime to a DateTime<Utc>
-    let datetime_utc: DateTime<Utc> = DateTime::from_naive_utc_and_offset(naive_datetime, Utc);
+    parse_naive(date_str)?.into_unix_nanos()
+}
 
-    // Convert to Unix time in nanoseconds
-    let unix_nanos = datetime_utc.timestamp_nanos_opt().unwrap();
+/// Parses a date or datetime without an offset as local time in `tz`.
+pub fn local_to_unix_nanos(date_str: &str, tz: Tz) -> Result<i64> {
+    naive_to_unix_nanos(parse_naive(date_str.trim())?, tz)
+}
 
-    Ok(unix_nanos)
+/// Converts a local datetime in `tz` to nanoseconds since the Unix epoch.
+pub fn naive_to_unix_nanos(naive: NaiveDateTime, tz: Tz) -> Result<i64> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(datetime) => datetime.into_unix_nanos(),
+        // Repeated hour when clocks fall back, take the first occurrence
+        LocalResult::Ambiguous(earliest, _) => earliest.into_unix_nanos(),
+        LocalResult::None => Err(Error::InvalidDateFormat(format!(
+            "'{}' does not exist in {} (skipped by a clock change)",
+            naive, tz
+        ))),
+    }
 }
 
+/// Parses an IANA timezone name such as `America/Chicago`.
+pub fn parse_timezone(name: &str) -> Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|_| Error::InvalidDateFormat(format!("Unknown timezone '{}'", name)))
+}
+
+fn parse_naive(date_str: &str) -> Result<NaiveDateTime> {
+    if let Ok(date) = NaiveDate::parse_from_str(date_str, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap()); // Set time to midnight
+    }
+
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(date_str, format).ok())
+        .ok_or_else(|| {
+            Error::InvalidDateFormat(format!(
+                "Invalid datetime format '{}'. Expected YYYY-MM-DD, YYYY-MM-DD HH:MM:SS[.f], RFC 3339, or a datetime followed by an IANA timezone",
+                date_str
+            ))
+        })
+}
+
+/// Formats as `YYYY-MM-DD HH:MM:SS[.f]` in UTC, keeping any fractional seconds.
 pub fn unix_nanos_to_date(unix_nanos: i64) -> Result<String> {
     // Convert the Unix nanoseconds to a DateTime<Utc>
     let datetime_utc: DateTime<Utc> = Utc.timestamp_nanos(unix_nanos);
 
-    // Format the DateTime<Utc> to a string in the format "YYYY-MM-DD HH:MM:SS"
-    let formatted_date = datetime_utc.format("%Y-%m-%d %H:%M:%S").to_string();
+    let formatted_date = datetime_utc.format("%Y-%m-%d %H:%M:%S%.f").to_string();
 
     Ok(formatted_date)
 }
 
+/// Formats as RFC 3339 in UTC with nanosecond precision, e.g. `2021-11-01T01:01:01.000000000Z`.
+pub fn unix_nanos_to_rfc3339(unix_nanos: i64) -> String {
+    Utc.timestamp_nanos(unix_nanos)
+        .to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Formats as RFC 3339 with the offset of `tz`, with nanosecond precision.
+pub fn unix_nanos_to_rfc3339_tz(unix_nanos: i64, tz: Tz) -> String {
+    Utc.timestamp_nanos(unix_nanos)
+        .with_timezone(&tz)
+        .to_rfc3339_opts(SecondsFormat::Nanos, false)
+}
+
+/// Replaces the file at `path` by writing `<path>.tmp` and renaming it over,
+/// so a crash never leaves a partial file.
+pub(crate) async fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    fs::write(&temp_path, contents).await?;
+    fs::rename(&temp_path, path).await?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,4 +185,79 @@ mod tests {
         assert_eq!("2021-11-01 01:01:01", iso);
         Ok(())
     }
+
+    #[test]
+    fn test_into_unix_nanos() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2021, 11, 1).unwrap();
+        let datetime = Utc.with_ymd_and_hms(2021, 11, 1, 1, 1, 1).unwrap();
+        let offset = chrono::FixedOffset::east_opt(3600)
+            .unwrap()
+            .with_ymd_and_hms(2021, 11, 1, 2, 1, 1)
+            .unwrap();
+
+        // Validate
+        assert_eq!(date.into_unix_nanos()?, 1635724800000000000);
+        assert_eq!(datetime.into_unix_nanos()?, 1635728461000000000);
+        assert_eq!(offset.into_unix_nanos()?, 1635728461000000000);
+        assert_eq!(5_i64.into_unix_nanos()?, 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rfc3339_to_unix_nanos() -> Result<()> {
+        // Validate
+        assert_eq!(
+            date_to_unix_nanos("2021-11-01T01:01:01.123456789Z")?,
+            1635728461123456789
+        );
+        assert_eq!(
+            date_to_unix_nanos("2021-10-31T21:01:01.5-04:00")?,
+            1635728461500000000
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fractional_to_unix_nanos() -> Result<()> {
+        // Validate
+        assert_eq!(
+            date_to_unix_nanos("2021-11-01 01:01:01.000000001")?,
+            1635728461000000001
+        );
+        assert_eq!(date_to_unix_nanos("2021-11-01T01:01")?, 1635728460000000000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_timezone_to_unix_nanos() -> Result<()> {
+        // Validate
+        assert_eq!(
+            date_to_unix_nanos("2024-01-02 09:30 America/New_York")?,
+            date_to_unix_nanos("2024-01-02T14:30:00Z")?
+        );
+        assert_eq!(
+            date_to_unix_nanos("2024-07-02 09:30:00 America/New_York")?,
+            date_to_unix_nanos("2024-07-02T13:30:00Z")?
+        );
+        assert!(date_to_unix_nanos("2024-03-10 02:30 America/New_York").is_err());
+        assert!(date_to_unix_nanos("2024-01-02 09:30 Mars/Olympus").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unix_to_date_nanos() -> Result<()> {
+        let unix = 1635728461000000001;
+
+        // Validate
+        assert_eq!(unix_nanos_to_date(unix)?, "2021-11-01 01:01:01.000000001");
+        assert_eq!(
+            unix_nanos_to_rfc3339(unix),
+            "2021-11-01T01:01:01.000000001Z"
+        );
+        assert_eq!(
+            unix_nanos_to_rfc3339_tz(unix, parse_timezone("America/Chicago")?),
+            "2021-10-31T20:01:01.000000001-05:00"
+        );
+        Ok(())
+    }
 }
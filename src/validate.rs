@@ -0,0 +1,290 @@
+use crate::stream::{decode_record, header, ts_recv, RecordBuffer};
+use mbn::record_enum::RecordEnum;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+/// Problem found in a record buffer before it is uploaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The buffer can't be decoded from this record on.
+    Undecodable(String),
+    /// `ts_recv` went backwards from the previous record.
+    NonMonotonicTsRecv {
+        previous: u64,
+        ts_recv: u64,
+    },
+    UnknownInstrument(u32),
+    /// Byte for byte copy of an earlier record in the batch.
+    Duplicate {
+        first: u64,
+    },
+    /// Price at the limits of `i64`, the undefined price sentinel or the
+    /// result of an overflow. Zero and negative prices are valid, e.g. for
+    /// spreads.
+    InvalidPrice(i64),
+    InvalidSize(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationIssue {
+    /// Position of the record in the buffer, starting at 0.
+    pub index: u64,
+    pub kind: IssueKind,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "record {}: ", self.index)?;
+        match &self.kind {
+            IssueKind::Undecodable(e) => write!(f, "undecodable ({})", e),
+            IssueKind::NonMonotonicTsRecv { previous, ts_recv } => {
+                write!(f, "ts_recv {} is before {}", ts_recv, previous)
+            }
+            IssueKind::UnknownInstrument(id) => write!(f, "unknown instrument id {}", id),
+            IssueKind::Duplicate { first } => write!(f, "duplicate of record {}", first),
+            IssueKind::InvalidPrice(price) => write!(f, "invalid price {}", price),
+            IssueKind::InvalidSize(size) => write!(f, "invalid size {}", size),
+        }
+    }
+}
+
+/// Result of checking a buffer passed to `create_mbp`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// Records decoded from the buffer.
+    pub records: u64,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} issue(s) in {} record(s)",
+            self.issues.len(),
+            self.records
+        )?;
+        for issue in &self.issues {
+            write!(f, "; {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prices and size of a record, with whether the size must be non-zero.
+/// Trades must carry a size, book updates such as cancels may not.
+fn quantities(record: &RecordEnum) -> (Vec<i64>, u64, bool) {
+    match record {
+        RecordEnum::Mbp1(msg) | RecordEnum::Tbbo(msg) => (vec![msg.price], msg.size as u64, false),
+        RecordEnum::Trade(msg) => (vec![msg.price], msg.size as u64, true),
+        RecordEnum::Bbo(msg) => (vec![msg.price], msg.size as u64, false),
+        RecordEnum::Ohlcv(msg) => (
+            vec![msg.open, msg.high, msg.low, msg.close],
+            msg.volume,
+            false,
+        ),
+    }
+}
+
+fn is_invalid_price(price: i64) -> bool {
+    price == i64::MAX || price == i64::MIN
+}
+
+fn record_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Checks a records-only mbn buffer, such as the one passed to `create_mbp`,
+/// against the ids in `instrument_ids`.
+///
+/// Checking stops at the first record that can't be decoded, since the
+/// length of anything after it is unknown.
+pub fn validate_records(data: &[u8], instrument_ids: &HashSet<u32>) -> ValidationReport {
+    let mut buffer = RecordBuffer::without_metadata();
+    buffer.push(data);
+
+    let mut report = ValidationReport::default();
+    // Hash of each record to its index and position in `data`, the bytes are
+    // compared on a hash match so collisions aren't reported as duplicates
+    let mut seen: HashMap<u64, (u64, Range<usize>)> = HashMap::new();
+    let mut offset = 0;
+    let mut previous_ts_recv = None;
+
+    loop {
+        let index = report.records;
+        let mut issue = |kind| report.issues.push(ValidationIssue { index, kind });
+
+        let bytes = match buffer.next_record_bytes() {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => {
+                if let Err(e) = buffer.finish() {
+                    issue(IssueKind::Undecodable(e.to_string()));
+                }
+                break;
+            }
+            Err(e) => {
+                issue(IssueKind::Undecodable(e.to_string()));
+                break;
+            }
+        };
+        let record = match decode_record(bytes) {
+            Ok(record) => record,
+            Err(e) => {
+                issue(IssueKind::Undecodable(e.to_string()));
+                break;
+            }
+        };
+
+        let range = offset..offset + bytes.len();
+        let hash = record_hash(bytes);
+        offset = range.end;
+        match seen.get(&hash) {
+            Some((first, first_range)) if data[first_range.clone()] == *bytes => {
+                issue(IssueKind::Duplicate { first: *first });
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(hash, (index, range));
+            }
+        }
+
+        let instrument_id = header(&record).instrument_id;
+        if !instrument_ids.contains(&instrument_id) {
+            issue(IssueKind::UnknownInstrument(instrument_id));
+        }
+        let ts_recv = ts_recv(&record);
+        if let Some(previous) = previous_ts_recv {
+            if ts_recv < previous {
+                issue(IssueKind::NonMonotonicTsRecv { previous, ts_recv });
+            }
+        }
+        let (prices, size, requires_size) = quantities(&record);
+        if let Some(price) = prices.into_iter().find(|p| is_invalid_price(*p)) {
+            issue(IssueKind::InvalidPrice(price));
+        }
+        if requires_size && size == 0 {
+            issue(IssueKind::InvalidSize(size));
+        }
+
+        previous_ts_recv = Some(ts_recv);
+        report.records += 1;
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::historical::Historical;
+    use crate::stream::tests::{mbp, records};
+    use crate::Error;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_records() {
+        let mut undefined = mbp(1, 4);
+        undefined.price = i64::MAX;
+        let mut spread = mbp(1, 5);
+        spread.price = -3;
+        let data = records(&[
+            mbp(1, 2),
+            mbp(1, 2),
+            mbp(2, 3),
+            mbp(1, 1),
+            undefined,
+            spread,
+        ]);
+
+        // Test
+        let report = validate_records(&data, &HashSet::from([1]));
+
+        // Validate
+        assert_eq!(report.records, 6);
+        assert_eq!(
+            report.issues,
+            vec![
+                ValidationIssue {
+                    index: 1,
+                    kind: IssueKind::Duplicate { first: 0 },
+                },
+                ValidationIssue {
+                    index: 2,
+                    kind: IssueKind::UnknownInstrument(2),
+                },
+                ValidationIssue {
+                    index: 3,
+                    kind: IssueKind::NonMonotonicTsRecv {
+                        previous: 3,
+                        ts_recv: 1,
+                    },
+                },
+                ValidationIssue {
+                    index: 4,
+                    kind: IssueKind::InvalidPrice(i64::MAX),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_truncated() {
+        let data = records(&[mbp(1, 1), mbp(1, 2)]);
+
+        // Test
+        let report = validate_records(&data[..data.len() - 1], &HashSet::from([1]));
+
+        // Validate
+        assert_eq!(report.records, 1);
+        assert!(matches!(
+            report.issues[..],
+            [ValidationIssue {
+                index: 1,
+                kind: IssueKind::Undecodable(_),
+            }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_mbp_checked_rejects() -> crate::Result<()> {
+        let mut server = mockito::Server::new_async().await;
+        let _instruments = server
+            .mock("GET", "/historical/instruments/list")
+            .with_status(200)
+            .with_body(
+                json!({"status": "success", "message": "", "code": 200, "data": []}).to_string(),
+            )
+            .create_async()
+            .await;
+        let upload = server
+            .mock("POST", "/historical/mbp/create")
+            .expect(0)
+            .create_async()
+            .await;
+        let client = Historical::new(&server.url());
+
+        // Test
+        let result = client.create_mbp_checked(&records(&[mbp(1, 1)])).await;
+
+        // Validate
+        upload.assert_async().await;
+        match result {
+            Err(Error::InvalidRecords(report)) => {
+                assert_eq!(report.issues[0].kind, IssueKind::UnknownInstrument(1))
+            }
+            other => panic!("Expected InvalidRecords, got {:?}", other),
+        }
+        Ok(())
+    }
+}
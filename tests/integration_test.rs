@@ -6,22 +6,9 @@ use mbn::records::{BidAskPair, Mbp1Msg, RecordHeader};
 use mbn::symbols::Instrument;
 use mbn::symbols::Vendors;
 use midas_client::historical::Historical;
-use regex::Regex;
 use serial_test::serial;
 use std::path::PathBuf;
 
-fn get_id_from_string(message: &str) -> Option<i32> {
-    let re = Regex::new(r"\d+$").unwrap();
-
-    if let Some(captures) = re.captures(message) {
-        if let Some(matched) = captures.get(0) {
-            let number: i32 = matched.as_str().parse().unwrap();
-            return Some(number);
-        }
-    }
-    None
-}
-
 async fn create_dummy_instrument(client: &Historical) -> anyhow::Result<i32> {
     // Create instrument
     let instrument = Instrument::new(
@@ -37,8 +24,7 @@ async fn create_dummy_instrument(client: &Historical) -> anyhow::Result<i32> {
     );
 
     let create_response = client.create_symbol(&instrument).await?;
-    let id = get_id_from_string(&create_response.message).expect("Error getting id from message.");
-    Ok(id)
+    Ok(create_response.data.expect("Expected an id").id)
 }
 
 async fn create_dummy_records_file(filename: &PathBuf) -> anyhow::Result<i32> {